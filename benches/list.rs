@@ -0,0 +1,75 @@
+//! Times `--list` and searching against a cache the size of github/gitignore,
+//! which only needs the names of the templates and not their content.
+
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use tempfile::TempDir;
+
+/// Roughly the number of templates in github/gitignore, and the lines of a
+/// long one like `VisualStudio`.
+const ROOT_TEMPLATES: usize = 160;
+const GLOBAL_TEMPLATES: usize = 70;
+const COMMUNITY_TEMPLATES: usize = 110;
+const LINES_PER_TEMPLATE: usize = 400;
+
+fn write_template(dir: &Path, name: &str) {
+    fs::create_dir_all(dir).unwrap();
+    let content: String = (0..LINES_PER_TEMPLATE)
+        .map(|i| format!("# Generated files of {name}\n/build-{i}/\n"))
+        .collect();
+    fs::write(dir.join(format!("{name}.gitignore")), content).unwrap();
+}
+
+/// A cache with as many templates as github/gitignore, including the nested
+/// `Global/` and `community/` ones.
+fn full_cache() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    let repo = dir.path().join("cache/github_gitignore_repo");
+    for i in 0..ROOT_TEMPLATES {
+        write_template(&repo, &format!("Language{i}"));
+    }
+    for i in 0..GLOBAL_TEMPLATES {
+        write_template(&repo.join("Global"), &format!("Editor{i}"));
+    }
+    for i in 0..COMMUNITY_TEMPLATES {
+        write_template(
+            &repo.join(format!("community/Group{}", i % 10)),
+            &format!("Framework{i}"),
+        );
+    }
+    fs::create_dir_all(dir.path().join("config/templates")).unwrap();
+    dir
+}
+
+fn run(dir: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .current_dir(dir)
+        .args(args)
+        .arg("--cache-dir")
+        .arg(dir.join("cache"))
+        .arg("--config-dir")
+        .arg(dir.join("config"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn list(c: &mut Criterion) {
+    let cache = full_cache();
+    c.bench_function("list all", |b| {
+        b.iter(|| run(cache.path(), &["--list", "--format", "plain"]))
+    });
+    c.bench_function("list matching", |b| {
+        b.iter(|| run(cache.path(), &["--list", "--format", "plain", "framework1"]))
+    });
+}
+
+criterion_group!(benches, list);
+criterion_main!(benches);
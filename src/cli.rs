@@ -6,50 +6,370 @@ use clap_complete::{Generator, Shell, generate};
 #[derive(Parser, Debug)]
 #[clap(name = "git-ignore", about, version, author)]
 #[clap(args_conflicts_with_subcommands = true)]
+// Lets explicit arguments override the defaults from `GIT_IGNORE_ARGS`.
+#[clap(args_override_self = true)]
+// `--output` and `--force` imply `--write`, see `main`.
+#[command(group(clap::ArgGroup::new("write_target").args(["write", "output", "force"]).multiple(true)))]
+#[command(group(clap::ArgGroup::new("prepend_target").args(["write", "output", "diff"]).multiple(true)))]
 /// Quickly and easily add templates to .gitignore
 pub struct Cli {
-    /// List <templates> or all available templates (uses gitignore.io cache).
+    /// List <templates> or all available templates (uses the local github/gitignore cache).
     #[arg(short, long)]
     pub list: bool,
-    /// Update templates by fetching them from gitignore.io
+    /// Output format used when listing templates.
+    #[arg(long, value_enum, default_value_t = ListFormat::Pretty)]
+    pub format: ListFormat,
+    /// List templates as a tree of their path segments, like `community/Golang/Hugo`.
+    #[arg(long, requires = "list", conflicts_with_all = ["format", "count_only"])]
+    pub tree: bool,
+    /// Only list one kind of template.
+    #[arg(long, value_enum, requires = "list")]
+    pub only: Option<Kind>,
+    /// Only print the number of listed templates, failing if there are none.
+    #[arg(long, requires = "list")]
+    pub count_only: bool,
+    /// Update the local cache by cloning or pulling the github/gitignore repository.
     #[arg(short = 'u', long)]
     pub update: bool,
-    /// Autodetect templates based on the existing files (uses gitignore.io cache).
+    /// Skip updating the cache instead of waiting when another process is already updating it.
+    /// Fails instead if the cache doesn't exist yet.
+    #[arg(long)]
+    pub no_wait: bool,
+    /// Give up on `git clone` or `git pull` when updating the cache after this many seconds.
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    pub git_timeout: u64,
+    /// Warn when the cache is older than this many days, overrides `cache_max_age` from the config.
+    #[arg(long, value_name = "DAYS")]
+    pub max_age: Option<u64>,
+    /// Autodetect templates based on the existing files (uses the local github/gitignore cache).
     #[arg(short, long)]
     pub auto: bool,
-    /// Write to `.gitignore` file instead of stdout.
-    /// For direct template fetching (e.g., `gi rust`), this appends to .gitignore.
-    /// For gitignore.io cache operations, behavior depends on other flags.
+    /// Keep running and append the templates for newly detected project files to `.gitignore`.
+    #[arg(long, requires_all = ["auto", "write_target"])]
+    pub watch: bool,
+    /// Autodetect the templates of each subdirectory of the current directory, like
+    /// `--auto`, and write them to a `.gitignore` in that subdirectory.
+    #[arg(long, requires_all = ["auto", "write_target"], conflicts_with = "watch")]
+    pub per_directory: bool,
+    /// Write to `.gitignore` file instead of stdout, appending to it if it already exists.
     #[arg(short, long)]
     pub write: bool,
-    /// Forcefully overwrite existing `.gitignore` file when used with gitignore.io cache operations.
-    /// Not used by direct GitHub template fetching mode (which always appends if -w is active).
-    #[arg(short, long, requires = "write")]
+    /// Write to this file instead of `.gitignore`, implies `--write`. Can be given
+    /// multiple times to update several files, like a global excludes file too.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["per_directory", "watch"]
+    )]
+    pub output: Vec<std::path::PathBuf>,
+    /// The encoding of a newly created `.gitignore`, `utf8-bom` adds a byte
+    /// order mark for Windows tools that expect one. Appending never adds it.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ENCODING",
+        default_value_t = OutputEncoding::Utf8,
+        requires = "write_target"
+    )]
+    pub output_encoding: OutputEncoding,
+    /// Anchor the patterns of the templates under this directory, like
+    /// `frontend`, for a `.gitignore` at the root of a monorepo.
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["per_directory", "watch"])]
+    pub scope: Option<String>,
+    /// Print to stdout even if `default_write` is set in the config.
+    #[arg(long, conflicts_with = "write_target")]
+    pub no_write: bool,
+    /// Refuse to write a `.gitignore` outside of a git repository.
+    #[arg(long, requires = "write_target")]
+    pub require_git_repo: bool,
+    /// After writing, print the lines that were added to `.gitignore`, grouped
+    /// by the template they came from.
+    #[arg(long, requires = "write_target")]
+    pub show_added: bool,
+    /// Exit with an error if the templates add no new lines, because they are
+    /// empty, missing or already in `.gitignore`.
+    #[arg(long, conflicts_with_all = ["list", "diff"])]
+    pub fail_on_empty: bool,
+    /// Overwrite an existing `.gitignore` file instead of appending to it, implies
+    /// `--write`. With `--combine-into`, replaces an existing user template.
+    #[arg(short, long)]
     pub force: bool,
+    /// Insert the new lines at the top of `.gitignore`, after its leading comments,
+    /// instead of appending them.
+    #[arg(long, requires = "prepend_target", conflicts_with = "force")]
+    pub prepend: bool,
+    /// How to merge the templates into an existing `.gitignore`.
+    #[arg(long, value_enum, default_value_t = MergeStrategy::Union, conflicts_with = "force")]
+    pub merge_strategy: MergeStrategy,
+    /// How the templates are printed to stdout.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Raw,
+        conflicts_with_all = ["write_target", "diff", "filter", "list"]
+    )]
+    pub output_format: OutputFormat,
+    /// When fetching templates directly, also skip patterns that are spelled
+    /// differently but match the same paths, like `/foo/bar` and `foo/bar`.
+    #[arg(long)]
+    pub smart_dedup: bool,
+    /// When fetching templates directly, keep single blank lines from the
+    /// templates in the printed output instead of dropping all of them.
+    #[arg(long, conflicts_with_all = ["write_target", "diff"])]
+    pub preserve_blanks: bool,
+    /// Print the keys of the templates the arguments resolve to, one per line,
+    /// after expanding globs and aliases and autodetecting, without their content.
+    #[arg(long, conflicts_with_all = ["write_target", "diff", "list", "filter"])]
+    pub resolve_only: bool,
+    /// Remove duplicate lines from the existing `.gitignore`, keeping the first
+    /// of each, and collapse runs of blank lines, without adding any templates.
+    #[arg(long, conflicts_with_all = ["templates", "auto", "write_target", "diff", "list", "filter"])]
+    pub tidy: bool,
+    /// Fail instead of using more templates than this, after expanding globs
+    /// and aliases.
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    pub limit_templates: usize,
+    /// Save the combined content of the templates as a new user template.
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["write", "output", "list", "filter", "verify"]
+    )]
+    pub combine_into: Option<String>,
+    /// Keep the templates in the given order, instead of moving `Global/` templates last.
+    #[arg(long)]
+    pub preserve_order: bool,
+    /// Don't add the "Sourced from" banner in front of the templates.
+    #[arg(long)]
+    pub no_banner: bool,
+    /// Print only the patterns, without the banner, the comments of the
+    /// templates or blank lines, for piping into other tools.
+    #[arg(
+        long,
+        conflicts_with_all = ["write_target", "diff", "list", "filter", "flatten_global", "preserve_blanks"]
+    )]
+    pub bare: bool,
+    /// Add a footer with the date and cache commit at the end of the written
+    /// `.gitignore`, replacing the one added before.
+    #[arg(long)]
+    pub footer: bool,
+    /// Don't add the footer, even if `footer_template` is set in the config.
+    #[arg(long, conflicts_with = "footer")]
+    pub no_footer: bool,
+    /// How template names are matched, overrides `template_case` in the config.
+    #[arg(long, value_enum, value_name = "CASE")]
+    pub template_case: Option<TemplateCase>,
+    /// Look templates up in a subdirectory of github/gitignore first, like
+    /// `community`, falling back to the name as given if it isn't there.
+    #[arg(long, value_name = "DIR")]
+    pub namespace: Option<String>,
+    /// Leave out `Global/` templates, like editor and OS specific ones, also when
+    /// they are autodetected, matched by a glob or part of an alias.
+    #[arg(long)]
+    pub no_global: bool,
+    /// Leave the `Global/` prefix out of template names in the banner.
+    #[arg(long, conflicts_with = "no_banner")]
+    pub flatten_global: bool,
+    /// Don't add a banner when the `.gitignore` already has one.
+    #[arg(long)]
+    pub append_header_once: bool,
+    /// Where to fetch templates from when they are requested directly.
+    #[arg(long, value_enum, default_value_t = Source::Github)]
+    pub source: Source,
+    /// Don't serve directly fetched templates from the response cache.
+    #[arg(long)]
+    pub no_cache: bool,
+    /// Print a unified diff of the changes `--write` would make to `.gitignore`, without
+    /// writing them.
+    #[arg(long, conflicts_with_all = ["write_target", "list", "filter", "verify", "combine_into"])]
+    pub diff: bool,
+    /// Check that the generated sections of `.gitignore` match their templates,
+    /// printing the differences and failing if they don't.
+    #[arg(long, conflicts_with_all = ["write_target", "list", "filter", "templates"])]
+    pub verify: bool,
+    /// Print the URL each template would be fetched from, without fetching it.
+    #[arg(long, conflicts_with_all = ["write_target", "list", "filter"])]
+    pub print_url: bool,
+    /// Print the github/gitignore commit the local cache is at, and its date.
+    #[arg(long, conflicts_with_all = ["write_target", "list", "filter", "print_url"])]
+    pub print_cache_commit: bool,
+    /// Fetch from github/gitignore and print how many commits the cache is
+    /// behind, without updating it.
+    #[arg(long, conflicts_with_all = ["update", "write_target", "list", "filter", "print_url"])]
+    pub check_updates: bool,
+    /// Copy the templates used into this directory, with a manifest of their
+    /// names and the commit of the cache they were copied from.
+    #[arg(long, value_name = "DIR", conflicts_with = "from_vendored")]
+    pub vendor: Option<std::path::PathBuf>,
+    /// Read templates from a directory created with `--vendor` instead of the cache.
+    #[arg(long, value_name = "DIR", conflicts_with = "update")]
+    pub from_vendored: Option<std::path::PathBuf>,
+    /// Read templates from an existing checkout of github/gitignore, like a
+    /// submodule, instead of cloning it into the cache.
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["update", "from_vendored"])]
+    pub repo_path: Option<std::path::PathBuf>,
+    /// Explain what a name resolves to, a user template, alias or template, and
+    /// where its content is read from.
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["write_target", "list", "filter", "diff", "verify", "print_url", "templates"]
+    )]
+    pub explain: Option<String>,
+    /// Fail without writing anything if any of the requested templates can't be resolved.
+    #[arg(long)]
+    pub strict: bool,
+    /// Read an existing `.gitignore` from stdin and print it to stdout, augmented
+    /// with the new lines from the requested templates. Nothing is written to disk.
+    #[arg(long, conflicts_with_all = ["write_target", "list"])]
+    pub filter: bool,
+    /// Also skip lines that are already in this file, e.g. a parent directory's
+    /// `.gitignore`. Can be given more than once.
+    #[arg(long, value_name = "PATH")]
+    pub against: Vec<std::path::PathBuf>,
+    /// Also skip lines that are already in the repository's `.git/info/exclude`.
+    #[arg(long)]
+    pub respect_git_exclude: bool,
+    /// Use this directory for the template cache instead of the default.
+    #[arg(long, global = true, value_name = "DIR")]
+    pub cache_dir: Option<std::path::PathBuf>,
+    /// Use this directory for the configuration and user templates instead of the default.
+    #[arg(long, global = true, value_name = "DIR")]
+    pub config_dir: Option<std::path::PathBuf>,
+    /// Read additional templates from the `.gitignore` files in this directory.
+    #[arg(long, global = true, value_name = "DIR")]
+    pub template_dir: Option<std::path::PathBuf>,
+    /// Send downloads and git's requests through this proxy instead of the one
+    /// from `HTTPS_PROXY`, `HTTP_PROXY` or `ALL_PROXY`. Hosts in `NO_PROXY` are
+    /// still reached directly.
+    #[arg(long, global = true, value_name = "URL")]
+    pub proxy: Option<String>,
+    /// Quiet output, hides progress of the initial clone of the cache.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Refuse to write to a `.gitignore` that is a symlink instead of following it.
+    #[arg(long)]
+    pub no_follow_symlinks: bool,
+    /// Don't lock `.gitignore` while writing it, for filesystems without
+    /// support for file locks.
+    #[arg(long)]
+    pub no_lock: bool,
     /// Verbose output.
-    #[arg(short = 'v', long)]
+    #[arg(short = 'v', long, global = true)]
     pub verbose: bool,
     /// Debug output.
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub debug: bool,
     /// Configuration management
     #[command(subcommand)]
     pub cmd: Option<Cmds>,
-    /// Names of templates to show/search for
+    /// Names of templates to show/search for.
+    /// An argument of the form `@path` is replaced by the names listed in that file, one per line.
     pub templates: Vec<String>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListFormat {
+    /// Indented and colored by kind
+    Pretty,
+    /// One name per line, without colors or indentation
+    Plain,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum MergeStrategy {
+    /// Add the lines `.gitignore` doesn't contain yet
+    Union,
+    /// Add all lines, even if `.gitignore` already contains them
+    Append,
+    /// Replace the sections generated before, from the first banner on
+    Replace,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// The `.gitignore` content as is
+    #[default]
+    Raw,
+    /// Wrapped in a fenced ```gitignore code block, to paste into documentation
+    Markdown,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputEncoding {
+    /// UTF-8 without a byte order mark
+    #[default]
+    Utf8,
+    /// UTF-8 with a byte order mark
+    Utf8Bom,
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateCase {
+    /// Match template names ignoring case
+    #[default]
+    Insensitive,
+    /// Require the exact case, suggesting the name when only the case differs
+    Exact,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Source {
+    /// One file per template from github/gitignore
+    Github,
+    /// Combined templates from the gitignore.io API
+    #[value(name = "gitignore.io")]
+    GitignoreIo,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Kind {
+    /// Templates from github/gitignore
+    Templates,
+    /// User defined aliases
+    Aliases,
+    /// User defined templates
+    User,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Cmds {
     #[command(subcommand, visible_alias = "aliases")]
     Alias(AliasCmd),
     #[command(subcommand, visible_alias = "templates")]
     Template(TemplateCmd),
+    #[command(subcommand)]
+    Config(ConfigCmd),
     /// Initialize user configuration
     Init {
         /// Forcefully create config, possibly overwrite existing
         #[clap(long)]
         force: bool,
+        /// Download the config from this URL instead of creating an empty one
+        #[clap(long, value_name = "URL")]
+        from: Option<String>,
+    },
+    /// Show which templates would be autodetected, without fetching or writing
+    ///
+    /// Pass `--verbose` to also show which files triggered each template.
+    Detect {
+        /// Print a table of the detected templates and the files that triggered them.
+        #[arg(long)]
+        dry_run: bool,
+        #[command(subcommand)]
+        cmd: Option<DetectCmd>,
     },
     /// Generate shell completion
     Completion {
@@ -57,6 +377,9 @@ pub enum Cmds {
         #[clap(value_enum)]
         shell: Shell,
     },
+    /// Check whether a newer version of git-ignore has been released, without installing it
+    #[cfg(feature = "self-update-check")]
+    SelfUpdateCheck,
 }
 
 #[derive(Subcommand, Debug)]
@@ -69,12 +392,81 @@ pub enum Cmds {
 pub enum AliasCmd {
     /// List available aliases
     #[command(visible_alias = "ls")]
-    List,
+    List {
+        /// Also show the templates each alias expands to, marking the ones that don't exist.
+        #[arg(long)]
+        expanded: bool,
+        /// Print the aliases and their targets as a JSON array, without colors.
+        #[arg(long, conflicts_with = "expanded")]
+        json: bool,
+    },
+    /// Show the templates an alias expands to, following nested aliases
+    Show { name: String },
     /// Add a new alias
-    Add { name: String, aliases: Vec<String> },
+    Add {
+        name: String,
+        /// The templates the alias expands to, `-` reads them from stdin,
+        /// separated by whitespace.
+        aliases: Vec<String>,
+        /// Add the targets to an existing alias instead of replacing it.
+        #[arg(long)]
+        append: bool,
+        /// With `--append`, create the alias if it doesn't exist yet.
+        #[arg(long, requires = "append")]
+        create: bool,
+        /// Print the config as it would be after the change, like `config show`,
+        /// without writing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
     /// Remove an alias
     #[command(visible_alias = "rm")]
-    Remove { name: String },
+    Remove {
+        name: String,
+        /// Print the config as it would be after the change, without writing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DetectCmd {
+    #[command(subcommand, visible_alias = "rules")]
+    Rule(RuleCmd),
+}
+
+#[derive(Subcommand, Debug)]
+/// Manage user defined detection rules
+///
+/// A detection rule maps a file or folder name pattern to a template, and is
+/// used in addition to the built-in rules when autodetecting templates. The
+/// pattern supports `*` and `?` wildcards, so a rule `*.sln` for
+/// `VisualStudio` detects any Visual Studio solution file.
+pub enum RuleCmd {
+    /// List available detection rules
+    #[command(visible_alias = "ls")]
+    List,
+    /// Add a new detection rule
+    Add { pattern: String, template: String },
+    /// Remove a detection rule
+    #[command(visible_alias = "rm")]
+    Remove { pattern: String },
+}
+
+#[derive(Subcommand, Debug)]
+/// Inspect the user configuration
+pub enum ConfigCmd {
+    /// Check the config for errors, like missing template files
+    ///
+    /// Exits with an error if any problem is found.
+    Validate,
+    /// Print the configuration as it is used, with the defaults filled in and
+    /// the overrides from the command line applied
+    Show {
+        /// Print it as JSON instead of TOML.
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -90,10 +482,34 @@ pub enum TemplateCmd {
     /// Add a new template
     ///
     /// You'll need to edit the file created to finish creating a template
-    Add { name: String },
+    Add {
+        name: String,
+        /// Start from the content of an existing template instead of an empty file.
+        #[arg(long, conflicts_with_all = ["content", "content_file"])]
+        from: Option<String>,
+        /// Use this as the content of the template, `-` reads it from stdin.
+        #[arg(long, conflicts_with = "content_file")]
+        content: Option<String>,
+        /// Read the content of the template from this file.
+        #[arg(long, value_name = "PATH")]
+        content_file: Option<std::path::PathBuf>,
+        /// Store the template in the shared template directory, `--template-dir`
+        /// or `template_dir` in the config, instead of your own config.
+        #[arg(long)]
+        global: bool,
+        /// Print the config as it would be after the change, like `config show`,
+        /// without writing it or the template file.
+        #[arg(long, conflicts_with = "global")]
+        dry_run: bool,
+    },
     /// Remove a template
     #[command(visible_alias = "rm")]
-    Remove { name: String },
+    Remove {
+        name: String,
+        /// Print the config as it would be after the change, without writing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 pub fn print_completion<G: Generator>(generator: G, app: &mut Command) {
@@ -1,26 +1,61 @@
 use std::{
     cmp::Ordering,
+    collections::{BTreeMap, HashSet},
     // collections::HashMap, // No longer directly used here
     fmt::{Display, Write, write},
-    fs::{read_to_string, read_dir},
+    fs::{read_dir, read_to_string},
     hash::{Hash, Hasher},
     path::{Path, PathBuf},
-    sync::LazyLock,
+    sync::{LazyLock, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::{Context, Result};
 use colored::Colorize;
 use etcetera::AppStrategy;
 // serde is not directly used in this file anymore for Language struct
-// use serde::{Deserialize, Serialize}; 
+// use serde::{Deserialize, Serialize};
 
-use crate::{ignore::PROJECT_DIRS, user_data::UserData};
+use crate::{
+    cli::{Kind, ListFormat, Source, TemplateCase},
+    diff,
+    error::{Error, IoContext, Result},
+    ignore::{self, PROJECT_DIRS},
+    merge::trim_pattern,
+    user_data::{self, UserData},
+};
 
-pub static CACHE_DIR: LazyLock<PathBuf> = LazyLock::new(|| PROJECT_DIRS.cache_dir());
-pub static GIT_REPO_CACHE_DIR: LazyLock<PathBuf> =
-    LazyLock::new(|| CACHE_DIR.join("github_gitignore_repo"));
+/// Overrides the cache directory for the whole run, set from `--cache-dir`
+/// before [`CACHE_DIR`] is first accessed.
+pub static CACHE_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+pub static CACHE_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    CACHE_DIR_OVERRIDE
+        .get()
+        .cloned()
+        .unwrap_or_else(|| PROJECT_DIRS.cache_dir())
+});
+/// The name of the github/gitignore clone inside the cache directory.
+pub const GIT_REPO_DIR_NAME: &str = "github_gitignore_repo";
+/// Reads the templates from this directory instead of the github/gitignore
+/// clone, set from `--from-vendored` or `--repo-path`.
+pub static GIT_REPO_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+pub static GIT_REPO_CACHE_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    GIT_REPO_DIR_OVERRIDE
+        .get()
+        .cloned()
+        .unwrap_or_else(|| CACHE_DIR.join(GIT_REPO_DIR_NAME))
+});
 // CACHE_FILE is no longer needed as we're not using ignore.json from gitignore.io
 
+/// Checks that `dir`, given with `--repo-path`, is a checkout of github/gitignore,
+/// so a wrong path fails instead of silently leaving out all templates.
+pub fn check_repo_path(dir: &Path) -> Result<()> {
+    if dir.join("Global").is_dir() {
+        Ok(())
+    } else {
+        Err(Error::NotAGitignoreRepo(dir.to_path_buf()))
+    }
+}
+
 // Language struct is no longer needed as we parse files directly
 // #[derive(Deserialize, Serialize, Debug)]
 // pub struct Language {
@@ -34,6 +69,8 @@ pub static GIT_REPO_CACHE_DIR: LazyLock<PathBuf> =
 #[derive(Debug)]
 pub struct IgnoreData {
     pub data: Vec<Type>,
+    /// Whether template names have to match the case of the files.
+    template_case: TemplateCase,
 }
 
 fn read_templates_from_dir(dir_path: &Path, base_key_prefix: Option<&str>) -> Result<Vec<Type>> {
@@ -44,39 +81,108 @@ fn read_templates_from_dir(dir_path: &Path, base_key_prefix: Option<&str>) -> Re
         return Ok(templates);
     }
 
-    for entry in read_dir(dir_path).with_context(|| format!("Failed to read directory: {:?}", dir_path))? {
-        let entry = entry.with_context(|| format!("Failed to read directory entry in {:?}", dir_path))?;
+    for entry in
+        read_dir(dir_path).io_context(|| format!("Failed to read directory: {:?}", dir_path))?
+    {
+        let entry =
+            entry.io_context(|| format!("Failed to read directory entry in {:?}", dir_path))?;
         let path = entry.path();
-        if path.is_file() {
-            if let Some(extension) = path.extension() {
-                if extension == "gitignore" {
-                    if let Some(file_stem) = path.file_stem().and_then(|s| s.to_str()) {
-                        let key = match base_key_prefix {
-                            Some(prefix) => format!("{}/{}", prefix, file_stem),
-                            None => file_stem.to_string(),
-                        };
-                        let content = read_to_string(&path)
-                            .with_context(|| format!("Failed to read template file: {:?}", path))?;
-                        templates.push(Type::Template { key, content });
-                    }
-                }
-            }
+        if path.is_file()
+            && path.extension().is_some_and(|ext| ext == "gitignore")
+            && let Some(file_stem) = path.file_stem().and_then(|s| s.to_str())
+        {
+            let key = match base_key_prefix {
+                Some(prefix) => format!("{}/{}", prefix, file_stem),
+                None => file_stem.to_string(),
+            };
+            templates.push(Type::Template { key, path });
+        }
+    }
+    Ok(templates)
+}
+
+/// Reads the content of a template from github/gitignore when it's used.
+fn read_template(key: &str, path: &Path) -> Result<String> {
+    read_to_string(path).map_err(|source| Error::TemplateUnreadable {
+        key: key.to_string(),
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Like [`read_templates_from_dir`], but also reads the templates of all nested
+/// directories, keyed by their path relative to `dir_path`.
+fn read_templates_recursive(dir_path: &Path, key_prefix: &str) -> Result<Vec<Type>> {
+    let mut templates = read_templates_from_dir(dir_path, Some(key_prefix))?;
+    if !dir_path.is_dir() {
+        return Ok(templates);
+    }
+
+    for entry in
+        read_dir(dir_path).io_context(|| format!("Failed to read directory: {:?}", dir_path))?
+    {
+        let path = entry
+            .io_context(|| format!("Failed to read directory entry in {:?}", dir_path))?
+            .path();
+        if path.is_dir()
+            && let Some(dir_name) = path.file_name().and_then(|s| s.to_str())
+        {
+            templates.extend(read_templates_recursive(
+                &path,
+                &format!("{key_prefix}/{dir_name}"),
+            )?);
         }
     }
     Ok(templates)
 }
 
+/// Reads the templates of the github/gitignore checkout at `repo_dir`.
+pub fn cache_templates(repo_dir: &Path) -> Result<Vec<Type>> {
+    // Read templates from the root of the cloned gitignore repository
+    let mut templates = read_templates_from_dir(repo_dir, None)?;
+
+    // Read templates from the Global/ subdirectory of the cloned gitignore repository
+    templates.extend(read_templates_from_dir(
+        &repo_dir.join("Global"),
+        Some("Global"),
+    )?);
+
+    // Read templates from the nested community/ subdirectories, e.g. `community/Golang/Go`
+    templates.extend(read_templates_recursive(
+        &repo_dir.join("community"),
+        "community",
+    )?);
+    Ok(templates)
+}
+
 impl IgnoreData {
+    /// Reads the templates from the local cache and the user's config. This
+    /// never updates the cache, if it hasn't been cloned yet there are simply
+    /// no templates from github/gitignore, so listing and completion stay fast
+    /// and work offline.
     pub fn new(user_data: &UserData) -> Result<Self> {
-        let mut data: Vec<Type> = Vec::new();
+        let mut data = cache_templates(GIT_REPO_CACHE_DIR.as_path())?;
 
-        // Read templates from the root of the cloned gitignore repository
-        data.extend(read_templates_from_dir(GIT_REPO_CACHE_DIR.as_path(), None)?);
+        // Templates from the template directory are treated as user templates, so
+        // they take precedence over the ones from github/gitignore. The user's own
+        // templates take precedence over them in turn.
+        if let Some(template_dir) = user_data.template_dir() {
+            for template in read_templates_from_dir(&template_dir, None)? {
+                if let Type::Template { key, path } = template
+                    && !user_data.templates.contains_key(&key)
+                {
+                    let content = read_to_string(&path)
+                        .io_context(|| format!("Failed to read template file: {:?}", path))?;
+                    let (extends, content) = user_data::parse_extends(&content);
+                    data.push(Type::UserTemplate {
+                        key,
+                        content,
+                        extends,
+                    });
+                }
+            }
+        }
 
-        // Read templates from the Global/ subdirectory of the cloned gitignore repository
-        let global_dir_path = GIT_REPO_CACHE_DIR.join("Global");
-        data.extend(read_templates_from_dir(&global_dir_path, Some("Global"))?);
-        
         // If data is empty at this point, it means the cache might not be populated.
         // The `Core::update` logic (which will handle git clone/pull) should run before this,
         // or this function should handle the "not yet cloned" case gracefully (which it does by returning empty vec).
@@ -94,10 +200,11 @@ impl IgnoreData {
             .clone()
             .into_iter()
             .map(|(name, path)| {
-                let template = UserData::read_template(&path)?;
+                let (extends, content) = UserData::read_template(&path)?;
                 Ok(Type::UserTemplate {
                     key: name,
-                    content: template,
+                    content,
+                    extends,
                 })
             })
             .collect::<Result<_>>()?;
@@ -105,14 +212,89 @@ impl IgnoreData {
 
         data.sort_unstable();
 
-        Ok(IgnoreData { data })
+        Ok(IgnoreData {
+            data,
+            template_case: user_data.template_case(),
+        })
+    }
+
+    /// Where the templates were read from and how many of each kind were
+    /// loaded, to show what state a run starts from.
+    pub fn summary(&self) -> String {
+        let (mut templates, mut aliases, mut user_templates) = (0, 0, 0);
+        for entry in &self.data {
+            match entry {
+                Type::Template { .. } => templates += 1,
+                Type::Alias { .. } => aliases += 1,
+                Type::UserTemplate { .. } => user_templates += 1,
+            }
+        }
+        let dir = GIT_REPO_CACHE_DIR.as_path();
+        format!(
+            "Cache at {} ({}), loaded {templates} template(s), {aliases} alias(es) and {user_templates} user template(s)",
+            dir.display(),
+            if dir.is_dir() { "exists" } else { "missing" }
+        )
     }
 
     pub fn keys(&self) -> impl Iterator<Item = TypeName> {
         self.data.iter().map(TypeName::from)
     }
 
-    pub fn list_aliases(&self) {
+    /// The sorted, deduplicated names matching a wildcard `pattern`, ignoring case.
+    pub fn matching(&self, pattern: &str) -> Vec<String> {
+        let pattern = pattern.to_lowercase();
+        let mut names: Vec<String> = self
+            .data
+            .iter()
+            .map(|v| v.key())
+            .filter(|key| crate::detector::wildcard_match(&pattern, &key.to_lowercase()))
+            .map(String::from)
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Warns about user templates and aliases that hide an alias or template
+    /// of the same name. User templates take precedence over aliases, and
+    /// aliases over templates, which are matched ignoring case.
+    pub fn warn_shadowed(&self) {
+        let template = |name: &str| {
+            self.data.iter().find_map(|v| match v {
+                Type::Template { key, .. } if key.eq_ignore_ascii_case(name) => Some(key),
+                _ => None,
+            })
+        };
+
+        for kind in &self.data {
+            let shadowed = match kind {
+                Type::UserTemplate { key, .. } if self.get_alias(key).is_some() => {
+                    format!("alias {}", key.yellow().bold())
+                }
+                Type::UserTemplate { key, .. } | Type::Alias { key, .. } => match template(key) {
+                    Some(template) => format!("template {template}"),
+                    None => continue,
+                },
+                Type::Template { .. } => continue,
+            };
+            let kind_name = match kind {
+                Type::UserTemplate { .. } => "User template",
+                _ => "Alias",
+            };
+            eprintln!(
+                "{}: {} {} shadows {}",
+                "Warning".yellow(),
+                kind_name,
+                TypeName::from(kind),
+                shadowed
+            );
+        }
+    }
+
+    /// Lists the aliases with their targets. With `expanded`, each alias is
+    /// followed by the templates it expands to, see [`IgnoreData::expand_alias`].
+    pub fn list_aliases(&self, expanded: bool) {
         let aliases = self
             .data
             .iter()
@@ -131,9 +313,39 @@ impl IgnoreData {
                 self.get_alias(kind.key())
                     .expect("Found alias is missing, this is an internal error")
             );
+            if expanded {
+                self.print_expansion(kind.key());
+            }
         }
     }
 
+    /// Prints the aliases as a JSON array of `{"name": ..., "targets": [...]}`
+    /// objects, with the targets as they are written in the config.
+    pub fn print_aliases_json(&self) {
+        #[derive(serde::Serialize)]
+        struct Alias<'a> {
+            name: &'a str,
+            targets: Vec<String>,
+        }
+
+        let aliases = self
+            .data
+            .iter()
+            .filter(|v| matches!(v, Type::Alias { .. }))
+            .map(|kind| Alias {
+                name: kind.key(),
+                targets: self
+                    .get_alias(kind.key())
+                    .expect("Found alias is missing, this is an internal error"),
+            })
+            .collect::<Vec<_>>();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&aliases)
+                .expect("Aliases are always serializable, this is an internal error")
+        );
+    }
+
     pub fn list_templates(&self) {
         let templates = self
             .data
@@ -156,14 +368,263 @@ impl IgnoreData {
         }
     }
 
-    pub fn get_template(&self, name: &str) -> Option<String> {
+    pub fn show_alias(&self, name: &str) {
+        if self.get_alias(name).is_none() {
+            return println!("No alias named {} found", name.blue());
+        }
+
+        println!("{} =>", name.yellow().bold());
+        self.print_expansion(name);
+    }
+
+    fn print_expansion(&self, name: &str) {
+        for target in self.expand_alias(name) {
+            if target.resolved {
+                println!("  {}", target.key);
+            } else {
+                println!("  {}", format!("{} (not found)", target.key).red());
+            }
+        }
+    }
+
+    /// Recursively expands an alias into the ordered, deduplicated list of
+    /// concrete templates it resolves to. Targets resolve to user templates
+    /// first, then other aliases and finally templates; an alias that is
+    /// already being expanded resolves to the template of the same name, so
+    /// an alias `node => [node, deno]` works as expected.
+    pub fn expand_alias(&self, name: &str) -> Vec<AliasTarget> {
+        let mut result = Vec::new();
+        self.expand_alias_into(name, &mut vec![name.to_string()], &mut result);
+        result
+    }
+
+    /// The number of templates `names` resolve to, counting the templates an
+    /// alias expands to instead of the alias.
+    pub fn resolved_count(&self, names: &[String]) -> usize {
+        names
+            .iter()
+            .map(|name| match self.get_user_template(name) {
+                None if self.get_alias(name).is_some() => self.expand_alias(name).len(),
+                _ => 1,
+            })
+            .sum()
+    }
+
+    /// The keys of the user templates and templates `names` resolve to, in the
+    /// order their content is combined and without duplicates, along with the
+    /// names that don't resolve. Aliases are expanded, and with `no_global`
+    /// `Global/` templates are left out like [`get_templates`] does.
+    pub fn resolve(&self, names: &[String], no_global: bool) -> (Vec<String>, Vec<String>) {
+        let mut keys: Vec<String> = Vec::new();
+        let mut unresolved = Vec::new();
+        let mut push = |key: &str| {
+            let skipped = no_global && is_global(key);
+            if !skipped && !keys.iter().any(|k| k == key) {
+                keys.push(key.to_string());
+            }
+        };
+        for name in names {
+            if self.get_user_template(name).is_some() {
+                push(name);
+            } else if self.get_alias(name).is_some() {
+                for target in self.expand_alias(name) {
+                    match self.template_key(&target.key) {
+                        Some(key) if self.get_user_template(&target.key).is_none() => push(key),
+                        _ if target.resolved => push(&target.key),
+                        _ => unresolved.push(target.key),
+                    }
+                }
+            } else if let Some(key) = self.template_key(name) {
+                push(key);
+            } else {
+                unresolved.push(name.clone());
+            }
+        }
+        (keys, unresolved)
+    }
+
+    fn expand_alias_into(
+        &self,
+        name: &str,
+        stack: &mut Vec<String>,
+        result: &mut Vec<AliasTarget>,
+    ) {
+        let Some(targets) = self.get_alias(name) else {
+            return;
+        };
+
+        for target in targets {
+            if self.get_user_template(&target).is_none()
+                && !stack.contains(&target)
+                && self.get_alias(&target).is_some()
+            {
+                stack.push(target.clone());
+                self.expand_alias_into(&target, stack, result);
+                stack.pop();
+                continue;
+            }
+
+            if result.iter().any(|t| t.key == target) {
+                continue;
+            }
+            let resolved = self.get_user_template(&target).is_some() || self.has_template(&target);
+            result.push(AliasTarget {
+                key: target,
+                resolved,
+            });
+        }
+    }
+
+    /// Looks up a template by its key, ignoring case if there's no exact match,
+    /// falling back to the built-in [`SYNONYMS`] for common abbreviations like
+    /// `js` or `py`.
+    pub fn get_template(&self, name: &str) -> Result<Option<String>> {
+        let Some(name) = self.template_key(name) else {
+            return Ok(None);
+        };
         self.data
             .iter()
-            .find(|k| matches!(k,Type::Template { key, .. } if key == name))
-            .map(|v| match v {
-                Type::Template { content, .. } => content.clone(),
-                _ => unreachable!(),
+            .find_map(|v| match v {
+                Type::Template { key, path } if key == name => Some(read_template(key, path)),
+                _ => None,
             })
+            .transpose()
+    }
+
+    /// Whether `name` resolves to a template, without reading it, see
+    /// [`IgnoreData::get_template`].
+    pub fn has_template(&self, name: &str) -> bool {
+        self.template_key(name).is_some()
+    }
+
+    /// The keys of the templates from github/gitignore that `names` resolve to,
+    /// expanding aliases. User templates and unknown names are left out.
+    pub fn template_keys(&self, names: &[String]) -> Vec<String> {
+        let mut keys: Vec<String> = Vec::new();
+        for name in names {
+            if self.get_user_template(name).is_some() {
+                continue;
+            }
+            let targets = match self.get_alias(name) {
+                Some(_) => self.expand_alias(name).into_iter().map(|t| t.key).collect(),
+                None => vec![name.clone()],
+            };
+            for target in targets {
+                if self.get_user_template(&target).is_some() {
+                    continue;
+                }
+                if let Some(key) = self.template_key(&target)
+                    && !keys.iter().any(|k| k == key)
+                {
+                    keys.push(key.to_string());
+                }
+            }
+        }
+        keys
+    }
+
+    /// The key of the template `name` resolves to, see [`IgnoreData::get_template`].
+    pub fn template_key(&self, name: &str) -> Option<&str> {
+        let keys = || {
+            self.data.iter().filter_map(|v| match v {
+                Type::Template { key, .. } => Some(key.as_str()),
+                _ => None,
+            })
+        };
+        let find = |name: &str| {
+            keys().find(|key| *key == name).or_else(|| {
+                (self.template_case == TemplateCase::Insensitive)
+                    .then(|| keys().find(|key| key.eq_ignore_ascii_case(name)))
+                    .flatten()
+            })
+        };
+
+        find(name).or_else(|| synonym(name).and_then(find))
+    }
+
+    /// The `Global/` template with the same name as the root template a bare
+    /// `name` resolves to, if there is one. `name` gets the root template, so
+    /// the other one is easy to miss.
+    pub fn global_variant(&self, name: &str) -> Option<String> {
+        if name.contains('/') {
+            return None;
+        }
+        let key = self.template_key(name)?;
+        let global = format!("Global/{key}");
+        (!key.contains('/') && self.template_key(&global) == Some(global.as_str()))
+            .then_some(global)
+    }
+
+    /// `name` inside `namespace` if that is a template, `name` as given otherwise.
+    pub fn namespaced(&self, name: &str, namespace: &str) -> String {
+        with_namespace(name, namespace)
+            .filter(|namespaced| self.template_key(namespaced).is_some())
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// Prints what `name` resolves to and where its content is read from. User
+    /// templates take precedence over aliases, and aliases over templates.
+    pub fn explain(&self, name: &str, user_data: &UserData, source: Source) -> Result<()> {
+        if self.get_user_template(name).is_some() {
+            println!("{}", self.describe(name, user_data));
+        } else if let Some(targets) = self.get_alias(name) {
+            println!("{}: alias => {:?}", name.yellow().bold(), targets);
+            for target in self.expand_alias(name) {
+                println!("  {}", self.describe(&target.key, user_data));
+            }
+        } else if let Some(key) = self.template_key(name) {
+            println!("{}", self.describe(name, user_data));
+            println!("  url: {}", ignore::template_url(key, source));
+        } else {
+            return Err(Error::TemplateNotFound(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Describes the kind of the user template or template `name` and its path.
+    fn describe(&self, name: &str, user_data: &UserData) -> String {
+        if self.get_user_template(name).is_some() {
+            let path = user_data.template_path(name).unwrap_or_default();
+            format!(
+                "{}: user template at {}",
+                name.blue().bold(),
+                path.display()
+            )
+        } else if let Some(key) = self.template_key(name) {
+            let path = GIT_REPO_CACHE_DIR.join(format!("{key}.gitignore"));
+            format!("{name}: template at {}", path.display())
+        } else {
+            format!("{name}: not found").red().to_string()
+        }
+    }
+
+    /// The description of a template or user template, aliases have none.
+    pub fn description(&self, name: &TypeName) -> Option<String> {
+        self.data
+            .iter()
+            .find_map(|v| match (v, name) {
+                (Type::Template { key, path }, TypeName::Template(name)) if key == name => {
+                    read_template(key, path)
+                        .inspect_err(|e| eprintln!("{}: {e}", "Warning".yellow()))
+                        .ok()
+                }
+                (Type::UserTemplate { key, content, .. }, TypeName::UserTemplate(name))
+                    if key == name =>
+                {
+                    Some(content.clone())
+                }
+                _ => None,
+            })
+            .and_then(|content| description(&content))
+    }
+
+    /// A user template, alias or template whose name only differs from `name`
+    /// in case, to suggest when `name` doesn't match anything.
+    pub fn case_suggestion(&self, name: &str) -> Option<&str> {
+        self.data
+            .iter()
+            .map(Type::key)
+            .find(|key| key.eq_ignore_ascii_case(name))
     }
 
     pub fn get_alias(&self, name: &str) -> Option<Vec<String>> {
@@ -176,6 +637,74 @@ impl IgnoreData {
             })
     }
 
+    /// The content of the user template `name` preceded by the content of the
+    /// templates its `#!extends` directive names, as `(key, content)` pairs in
+    /// order. Bases are resolved like the names passed on the command line and
+    /// may extend other templates in turn, each is included once. A base that
+    /// is unknown or would include a template in itself is skipped with a warning.
+    pub fn user_template_parts(&self, name: &str) -> Result<Vec<(String, String)>> {
+        let mut parts = Vec::new();
+        self.user_template_parts_into(name, &mut vec![name.to_string()], &mut parts)?;
+        Ok(parts)
+    }
+
+    fn user_template_parts_into(
+        &self,
+        name: &str,
+        stack: &mut Vec<String>,
+        parts: &mut Vec<(String, String)>,
+    ) -> Result<()> {
+        let Some((content, extends)) = self.data.iter().find_map(|v| match v {
+            Type::UserTemplate {
+                key,
+                content,
+                extends,
+            } if key == name => Some((content, extends)),
+            _ => None,
+        }) else {
+            return Ok(());
+        };
+
+        for base in extends {
+            let targets = match self.get_user_template(base) {
+                None if self.get_alias(base).is_some() => {
+                    self.expand_alias(base).into_iter().map(|t| t.key).collect()
+                }
+                _ => vec![base.clone()],
+            };
+            for target in targets {
+                if stack.contains(&target) {
+                    eprintln!(
+                        "{}: User template {} extends {}, which includes it again, skipping it",
+                        "Warning".yellow(),
+                        name.blue().bold(),
+                        target.bold()
+                    );
+                } else if parts.iter().any(|(key, _)| *key == target) {
+                    continue;
+                } else if self.get_user_template(&target).is_some() {
+                    stack.push(target.clone());
+                    self.user_template_parts_into(&target, stack, parts)?;
+                    stack.pop();
+                } else if let Some(content) = self.get_template(&target)? {
+                    parts.push((target, content));
+                } else {
+                    eprintln!(
+                        "{}: User template {} extends unknown template {}",
+                        "Warning".yellow(),
+                        name.blue().bold(),
+                        target.bold()
+                    );
+                }
+            }
+        }
+        // A user template may consist of nothing but the directive.
+        if extends.is_empty() || !content.trim().is_empty() {
+            parts.push((name.to_string(), content.clone()));
+        }
+        Ok(())
+    }
+
     pub fn get_user_template(&self, name: &str) -> Option<String> {
         self.data
             .iter()
@@ -187,11 +716,31 @@ impl IgnoreData {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct AliasTarget {
+    pub key: String,
+    pub resolved: bool,
+}
+
+/// A template from github/gitignore, an alias or a user template. The content
+/// of templates is only read when it's used, since listing, searching and
+/// completion only need the key. The `extends` of a user template are the
+/// templates its `#!extends` directive names, see [`IgnoreData::user_template_parts`].
 #[derive(Debug, Clone)]
 pub enum Type {
-    Template { key: String, content: String },
-    Alias { key: String, aliases: Vec<String> },
-    UserTemplate { key: String, content: String },
+    Template {
+        key: String,
+        path: PathBuf,
+    },
+    Alias {
+        key: String,
+        aliases: Vec<String>,
+    },
+    UserTemplate {
+        key: String,
+        content: String,
+        extends: Vec<String>,
+    },
 }
 
 impl PartialEq for Type {
@@ -214,16 +763,12 @@ impl PartialOrd for Type {
 }
 
 impl Ord for Type {
+    /// Templates come first, then aliases, then user templates, each sorted by
+    /// key, so sorting gives the same order regardless of the input order.
     fn cmp(&self, other: &Self) -> Ordering {
-        match (self, other) {
-            (Type::UserTemplate { .. }, Type::UserTemplate { .. }) => Ordering::Equal,
-            (Type::UserTemplate { .. }, _) => Ordering::Greater,
-            (Type::Alias { .. }, Type::UserTemplate { .. }) => Ordering::Greater,
-            (Type::Alias { .. }, Type::Alias { .. }) => Ordering::Equal,
-            (Type::Alias { .. }, Type::Template { .. }) => Ordering::Less,
-            (Type::Template { .. }, Type::Template { .. }) => Ordering::Equal,
-            (Type::Template { .. }, _) => Ordering::Less,
-        }
+        self.kind_rank()
+            .cmp(&other.kind_rank())
+            .then_with(|| self.key().cmp(other.key()))
     }
 }
 
@@ -235,6 +780,14 @@ impl Type {
             | Self::UserTemplate { key, .. } => key,
         }
     }
+
+    fn kind_rank(&self) -> u8 {
+        match self {
+            Self::Template { .. } => 0,
+            Self::Alias { .. } => 1,
+            Self::UserTemplate { .. } => 2,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -291,75 +844,767 @@ impl Hash for TypeName {
 }
 
 impl TypeName {
-    fn inner(&self) -> &str {
+    /// The same kind of name, for display with the kind's color.
+    fn renamed(&self, name: &str) -> TypeName {
+        let name = name.to_string();
+        match self {
+            TypeName::Template(_) => TypeName::Template(name),
+            TypeName::Alias(_) => TypeName::Alias(name),
+            TypeName::UserTemplate(_) => TypeName::UserTemplate(name),
+        }
+    }
+
+    pub fn inner(&self) -> &str {
         match self {
             TypeName::Template(name) | TypeName::Alias(name) | TypeName::UserTemplate(name) => name,
         }
     }
 
+    pub fn is_kind(&self, kind: Kind) -> bool {
+        match kind {
+            Kind::Templates => matches!(self, TypeName::Template(_)),
+            Kind::Aliases => matches!(self, TypeName::Alias(_)),
+            Kind::User => matches!(self, TypeName::UserTemplate(_)),
+        }
+    }
+
     pub fn contains(&self, name: &str) -> bool {
         let inner = self.inner();
         inner.contains(name)
     }
 }
 
-pub fn list(data: &IgnoreData, names: &[String]) -> String {
-    let templates = data.keys();
+/// Whether `name` is one of the `Global/` templates, like editor and OS specific
+/// ones, ignoring case.
+pub fn is_global(name: &str) -> bool {
+    name.to_ascii_lowercase().starts_with("global/")
+}
 
-    let mut result = if names.is_empty() {
-        templates.into_iter().collect::<Vec<_>>()
-    } else {
-        let mut result = Vec::new();
+/// `name` inside the subdirectory `namespace`, or `None` if it already is.
+pub fn with_namespace(name: &str, namespace: &str) -> Option<String> {
+    let namespace = namespace.trim_end_matches('/');
+    let prefix = format!("{}/", namespace.to_ascii_lowercase());
+    (!name.to_ascii_lowercase().starts_with(&prefix)).then(|| format!("{namespace}/{name}"))
+}
 
-        for entry in templates {
-            for name in names {
-                if entry.contains(name) {
-                    result.push(entry.clone());
-                }
-            }
-        }
-        result
-    };
+/// Moves the `Global/` templates, like editor and OS specific ones, after the
+/// project specific templates, keeping the order within both.
+pub fn globals_last(names: &mut [String]) {
+    names.sort_by_key(|name| is_global(name));
+}
+
+/// Built-in abbreviations for common templates, consulted when a name doesn't
+/// match a template. User aliases and templates always take precedence.
+pub const SYNONYMS: &[(&str, &str)] = &[
+    ("c#", "VisualStudio"),
+    ("cpp", "C++"),
+    ("golang", "Go"),
+    ("js", "Node"),
+    ("kt", "Kotlin"),
+    ("py", "Python"),
+    ("rb", "Ruby"),
+    ("rs", "Rust"),
+];
+
+/// Returns the template key a built-in synonym stands for, ignoring case.
+pub fn synonym(name: &str) -> Option<&'static str> {
+    SYNONYMS
+        .iter()
+        .find(|(short, _)| short.eq_ignore_ascii_case(name))
+        .map(|(_, key)| *key)
+}
+
+/// Renders [`SYNONYMS`] for `--list --verbose`.
+pub fn list_synonyms() -> String {
+    SYNONYMS.iter().fold(
+        String::from("Built-in synonyms:\n"),
+        |mut s, (short, key)| {
+            writeln!(s, "  {short} => {key}").unwrap();
+            s
+        },
+    )
+}
+
+/// The first line of the comment block a template starts with, if any.
+fn description(content: &str) -> Option<String> {
+    content
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .take_while(|line| line.starts_with('#'))
+        .map(|line| line.trim_matches('#').trim())
+        .find(|line| !line.is_empty())
+        .map(String::from)
+}
 
+/// The sorted names containing any of `names`, or all of them.
+fn listed(data: &IgnoreData, names: &[String], only: Option<Kind>) -> Vec<TypeName> {
+    let mut result = data
+        .keys()
+        .filter(|key| only.is_none_or(|kind| key.is_kind(kind)))
+        .filter(|key| names.is_empty() || names.iter().any(|name| key.contains(name)))
+        .collect::<Vec<_>>();
     result.sort_unstable();
+    result
+}
 
-    result.into_iter().fold(String::new(), |mut s, r| {
-        writeln!(s, "  {r}").unwrap();
+/// The number of names [`list`] lists.
+pub fn count(data: &IgnoreData, names: &[String], only: Option<Kind>) -> usize {
+    listed(data, names, only).len()
+}
+
+/// Lists the names matching any of `names`, or all of them. With `verbose`,
+/// templates are listed with their description, see [`description`].
+pub fn list(
+    data: &IgnoreData,
+    names: &[String],
+    format: ListFormat,
+    only: Option<Kind>,
+    verbose: bool,
+) -> String {
+    let listed = listed(data, names, only);
+    listed.into_iter().fold(String::new(), |mut s, r| {
+        let description = verbose.then(|| data.description(&r)).flatten();
+        match (format, description) {
+            (ListFormat::Pretty, Some(description)) => {
+                writeln!(s, "  {r}  {}", description.dimmed()).unwrap()
+            }
+            (ListFormat::Pretty, None) => writeln!(s, "  {r}").unwrap(),
+            (ListFormat::Plain, Some(description)) => {
+                writeln!(s, "{}\t{description}", r.inner()).unwrap()
+            }
+            (ListFormat::Plain, None) => writeln!(s, "{}", r.inner()).unwrap(),
+        }
         s
     })
 }
 
-pub fn get_templates(data: &IgnoreData, names: &[String]) -> String {
+/// Like [`list`], but renders the names as a tree of their `/` separated
+/// segments, like `community/` > `Golang/` > `Hugo`. The directories of each
+/// level are listed before its templates.
+pub fn tree(data: &IgnoreData, names: &[String], only: Option<Kind>, verbose: bool) -> String {
+    #[derive(Default)]
+    struct Dir {
+        dirs: BTreeMap<String, Dir>,
+        names: Vec<(TypeName, TypeName)>,
+    }
+
+    fn render(dir: &Dir, depth: usize, data: &IgnoreData, verbose: bool, s: &mut String) {
+        let indent = "  ".repeat(depth + 1);
+        for (segment, sub_dir) in &dir.dirs {
+            writeln!(s, "{indent}{segment}/").unwrap();
+            render(sub_dir, depth + 1, data, verbose, s);
+        }
+        for (leaf, name) in &dir.names {
+            match verbose.then(|| data.description(name)).flatten() {
+                Some(description) => writeln!(s, "{indent}{leaf}  {}", description.dimmed()),
+                None => writeln!(s, "{indent}{leaf}"),
+            }
+            .unwrap();
+        }
+    }
+
+    let mut root = Dir::default();
+    for name in listed(data, names, only) {
+        let full = name.inner().to_string();
+        let (path, leaf) = full.rsplit_once('/').unwrap_or(("", &full));
+        let dir = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .fold(&mut root, |dir, segment| {
+                dir.dirs.entry(segment.to_string()).or_default()
+            });
+        dir.names.push((name.renamed(leaf), name));
+    }
+    let mut s = String::new();
+    render(&root, 0, data, verbose, &mut s);
+    s
+}
+
+/// Combines the content of the templates for `names`, also returning the names
+/// that produced no content: those that didn't match any user template, alias
+/// or template, and aliases none of whose targets exist. Aliases with targets
+/// that don't exist are reported as they are encountered.
+///
+/// The combined content is prefixed with `banner`, see [`render_banner`]. With
+/// `flatten_global`, `Global/` is left out of the names in the banner. With
+/// `no_global`, `Global/` templates are skipped, also when an alias expands to them.
+/// Fails if a template can't be read.
+pub fn get_templates(
+    data: &IgnoreData,
+    names: &[String],
+    banner: Option<&str>,
+    flatten_global: bool,
+    no_global: bool,
+) -> Result<(String, Vec<String>)> {
     let mut result = String::new();
+    let mut failed = Vec::new();
+    let mut push_content = |name: &str, content: &str| {
+        if content.trim().is_empty() {
+            eprintln!(
+                "{}: Template {} exists but is empty",
+                "Warning".yellow(),
+                name.bold()
+            );
+            return;
+        }
+        // Trailing whitespace trips up linters and is dropped by the direct
+        // fetch path as well.
+        let mut trimmed = content
+            .lines()
+            .map(trim_pattern)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if content.ends_with('\n') {
+            trimmed.push('\n');
+        }
+        let content = trimmed;
+        // Separate adjacent templates by a single blank line.
+        if !result.is_empty() {
+            if !result.ends_with('\n') {
+                result.push('\n');
+            }
+            if !result.ends_with("\n\n") && !content.starts_with('\n') {
+                result.push('\n');
+            }
+        }
+        result.push_str(&content);
+    };
 
     for name in names {
-        if let Some(val) = data.get_user_template(name) {
-            result.push_str(&val);
-        } else if let Some(val) = data.get_alias(name) {
-            for alias in val {
-                if let Some(val) = data.get_user_template(&alias) {
-                    result.push_str(&val);
-                } else if let Some(language) = data.get_template(&alias) {
-                    result.push_str(&language);
+        if no_global && is_global(name) {
+            continue;
+        }
+        if data.get_user_template(name).is_some() {
+            for (key, content) in data.user_template_parts(name)? {
+                push_content(&key, &content);
+            }
+        } else if data.get_alias(name).is_some() {
+            let mut resolved_any = false;
+            for alias in data.expand_alias(name).into_iter().map(|t| t.key) {
+                if no_global && is_global(&alias) {
+                    resolved_any = true;
+                } else if data.get_user_template(&alias).is_some() {
+                    for (key, content) in data.user_template_parts(&alias)? {
+                        push_content(&key, &content);
+                    }
+                    resolved_any = true;
+                } else if let Some(language) = data.get_template(&alias)? {
+                    push_content(&alias, &language);
+                    resolved_any = true;
                 } else {
-                    eprintln!("{}: No such alias", name.bold().yellow());
+                    eprintln!(
+                        "{}: Alias {} refers to unknown template {}",
+                        "Warning".yellow(),
+                        name.bold().yellow(),
+                        alias.bold()
+                    );
                 }
             }
-        } else if let Some(language) = data.get_template(name) {
-            result.push_str(&language);
+            if !resolved_any {
+                failed.push(name.clone());
+            }
+        } else if let Some(language) = data.get_template(name)? {
+            if let Some(global) = data.global_variant(name) {
+                let key = global.trim_start_matches("Global/");
+                eprintln!(
+                    "{}: Both {} and {} exist, using {}, pass {} for the other one",
+                    "Info".bold().green(),
+                    key.bold(),
+                    global.bold(),
+                    key,
+                    global.bold()
+                );
+            }
+            push_content(name, &language);
+        } else {
+            if let Some(suggestion) = data.case_suggestion(name) {
+                eprintln!(
+                    "{}: No template named {}, did you mean {}?",
+                    "Warning".yellow(),
+                    name.bold(),
+                    suggestion.bold()
+                );
+            }
+            failed.push(name.clone());
         }
     }
 
-    if !result.is_empty() {
+    if !result.is_empty()
+        && let Some(banner) = banner
+    {
         // Prepend a header indicating the source of the combined templates.
         // The actual content comes from individual files in github/gitignore.
-        let mut header = format!(
-            "\n\n### Sourced from github/gitignore for: {} ###\n",
-            names.join(", ")
-        );
+        let banner_names = banner_names(names, flatten_global, no_global);
+        let mut header = format!("\n\n{}\n", render_banner(banner, &banner_names));
         header.push_str(&result);
         result = header;
     }
 
-    result
+    Ok((result, failed))
+}
+
+/// The names [`get_templates`] lists in the banner for the requested `names`.
+pub fn banner_names(names: &[String], flatten_global: bool, no_global: bool) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| !(no_global && is_global(name)))
+        .map(|name| match name.strip_prefix("Global/") {
+            Some(name) if flatten_global => name.to_string(),
+            _ => name.clone(),
+        })
+        .collect()
+}
+
+/// The banner prepended to the output of [`get_templates`] by default.
+pub const DEFAULT_BANNER: &str = "### Sourced from github/gitignore for: {templates} ###";
+
+/// Renders a banner, replacing `{templates}` with the requested names and
+/// `{date}` with the current date (`YYYY-MM-DD`, UTC).
+pub fn render_banner(banner: &str, names: &[String]) -> String {
+    banner
+        .replace("{templates}", &names.join(", "))
+        .replace("{date}", &today())
+}
+
+/// The current date in UTC formatted as `YYYY-MM-DD`.
+pub fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Converts days since the Unix epoch to a `(year, month, day)` date, see
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The footer appended to a written `.gitignore` with `--footer` by default.
+pub const DEFAULT_FOOTER: &str =
+    "# Generated by git-ignore on {date} from github/gitignore {commit}";
+
+/// Renders a footer, replacing `{date}` with the current date and `{commit}`
+/// with the abbreviated commit the templates come from, `unknown` if it
+/// isn't known.
+pub fn render_footer(footer: &str, commit: Option<&str>) -> String {
+    footer
+        .replace("{date}", &today())
+        .replace("{commit}", commit.unwrap_or("unknown"))
+}
+
+/// Whether `line` was rendered from `footer`, see [`render_footer`]. The
+/// placeholders only match what they render to: `{date}` a `YYYY-MM-DD` date
+/// and `{commit}` an abbreviated commit hash or `unknown`.
+pub fn is_footer_line(line: &str, footer: &str) -> bool {
+    if let Some(footer) = footer.strip_prefix("{date}") {
+        let is_date = line.get(..10).is_some_and(|date| {
+            date.bytes().enumerate().all(|(i, b)| {
+                if i == 4 || i == 7 {
+                    b == b'-'
+                } else {
+                    b.is_ascii_digit()
+                }
+            })
+        });
+        return is_date && is_footer_line(&line[10..], footer);
+    }
+    if let Some(footer) = footer.strip_prefix("{commit}") {
+        if let Some(line) = line.strip_prefix("unknown")
+            && is_footer_line(line, footer)
+        {
+            return true;
+        }
+        let hex_len = line
+            .bytes()
+            .take_while(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(b))
+            .count();
+        return (4..=hex_len).any(|len| is_footer_line(&line[len..], footer));
+    }
+    match footer.chars().next() {
+        Some(c) => line
+            .strip_prefix(c)
+            .is_some_and(|line| is_footer_line(line, &footer[c.len_utf8()..])),
+        None => line.is_empty(),
+    }
+}
+
+/// `content` without the footer rendered from `footer`, which is only
+/// recognised as its last non-blank line.
+pub fn without_footer<'a>(content: &'a str, footer: &str) -> &'a str {
+    let trimmed = content.trim_end();
+    let (rest, last) = trimmed.rsplit_once('\n').unwrap_or(("", trimmed));
+    if is_footer_line(last.trim_end(), footer) {
+        rest
+    } else {
+        content
+    }
+}
+
+/// Turns `banner` into a wildcard pattern matching its rendered lines, along
+/// with the length of the text before and after the `{templates}` placeholder.
+fn banner_pattern(banner: &str) -> (String, usize, usize) {
+    // `{date}` always renders to `YYYY-MM-DD`, so it can be matched by length.
+    let banner = banner.replace("{date}", "??????????");
+    match banner.split_once("{templates}") {
+        Some((prefix, suffix)) => (
+            format!("{prefix}*{suffix}"),
+            prefix.chars().count(),
+            suffix.chars().count(),
+        ),
+        None => (banner.clone(), banner.chars().count(), 0),
+    }
+}
+
+/// Whether `line` was rendered from `banner`, see [`render_banner`].
+pub fn is_banner_line(line: &str, banner: &str) -> bool {
+    crate::detector::wildcard_match(&banner_pattern(banner).0, line)
+}
+
+/// Adds `names` to the templates listed in the last banner of `content`
+/// rendered from `banner`, keeping the rest of the line as it is. Lines
+/// appended without a banner of their own, see `--append-header-once`, then
+/// belong to that section.
+pub fn extend_last_banner(content: &str, banner: &str, names: &[String]) -> String {
+    if !banner.contains("{templates}") {
+        return content.to_string();
+    }
+    let (pattern, prefix_len, suffix_len) = banner_pattern(banner);
+    let mut lines: Vec<String> = content.split_inclusive('\n').map(String::from).collect();
+    let last_banner = lines.iter_mut().rev().find(|line| {
+        crate::detector::wildcard_match(&pattern, line.trim_end_matches(['\r', '\n']))
+    });
+    if let Some(line) = last_banner {
+        let text = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[text.len()..];
+        let chars: Vec<char> = text.chars().collect();
+        let suffix_start = chars.len().saturating_sub(suffix_len).max(prefix_len);
+        let mut templates: Vec<String> = chars[prefix_len..suffix_start]
+            .iter()
+            .collect::<String>()
+            .split(", ")
+            .map(String::from)
+            .collect();
+        for name in names {
+            if !templates.contains(name) {
+                templates.push(name.clone());
+            }
+        }
+        *line = format!(
+            "{}{}{}{ending}",
+            chars[..prefix_len].iter().collect::<String>(),
+            templates.join(", "),
+            chars[suffix_start..].iter().collect::<String>(),
+        );
+    }
+    lines.concat()
+}
+
+/// The sections of `content` generated with `banner`, as the templates named
+/// in their banner and their lines. A section starts at a banner line and runs
+/// until the next banner or the end of the file, the footer rendered from
+/// `footer` isn't part of any section.
+fn generated_sections<'a>(
+    content: &'a str,
+    banner: &str,
+    footer: Option<&str>,
+) -> Vec<(Vec<String>, Vec<&'a str>)> {
+    let (pattern, prefix_len, suffix_len) = banner_pattern(banner);
+
+    let content = footer.map_or(content, |footer| without_footer(content, footer));
+    let mut sections: Vec<(Vec<String>, Vec<&str>)> = Vec::new();
+    for line in content.lines() {
+        if crate::detector::wildcard_match(&pattern, line) {
+            let len = line.chars().count();
+            let names: String = line
+                .chars()
+                .skip(prefix_len)
+                .take(len.saturating_sub(prefix_len + suffix_len))
+                .collect();
+            sections.push((names.split(", ").map(String::from).collect(), Vec::new()));
+        } else if let Some((_, lines)) = sections.last_mut() {
+            lines.push(trim_pattern(line));
+        }
+    }
+    sections
+}
+
+/// The names to resolve the `templates` named in a banner by.
+fn section_keys(data: &IgnoreData, templates: &[String]) -> Vec<String> {
+    // Banners written with `--flatten-global` leave out the `Global/` prefix.
+    templates
+        .iter()
+        .map(|name| {
+            let global = format!("Global/{name}");
+            if !data.has_template(name) && data.has_template(&global) {
+                global
+            } else {
+                name.clone()
+            }
+        })
+        .collect()
+}
+
+/// The templates named in the banners of the sections of `content` generated
+/// with `banner`, each once.
+pub fn section_names(content: &str, banner: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    if !banner.contains("{templates}") {
+        return names;
+    }
+    for (templates, _) in generated_sections(content, banner, None) {
+        for name in templates {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// The current content of the templates the sections of `content` generated
+/// with `banner` were generated from, so [`merge::without_sections`] can tell
+/// them apart from lines added by hand.
+///
+/// [`merge::without_sections`]: crate::merge::without_sections
+pub fn section_templates(data: &IgnoreData, content: &str, banner: &str) -> Result<String> {
+    let mut templates = String::new();
+    if !banner.contains("{templates}") {
+        return Ok(templates);
+    }
+    for (names, _) in generated_sections(content, banner, None) {
+        let (content, _) = get_templates(data, &section_keys(data, &names), None, false, false)?;
+        templates.push_str(&content);
+        templates.push('\n');
+    }
+    Ok(templates)
+}
+
+/// A section of a generated `.gitignore` that no longer matches its templates.
+pub struct OutdatedSection {
+    /// The templates named in the section's banner.
+    pub templates: Vec<String>,
+    /// Templates that don't resolve anymore.
+    pub unknown: Vec<String>,
+    /// A unified diff from the section to what its templates resolve to now.
+    pub diff: String,
+}
+
+/// Finds the sections of `content` that were generated with `banner` and differ
+/// from what their templates currently resolve to, see [`generated_sections`].
+///
+/// Like for `--merge-strategy replace`, the lines of a section that its
+/// templates don't have now and didn't have before, as returned by `history`
+/// for their names, were added by hand and aren't reported.
+pub fn outdated_sections(
+    data: &IgnoreData,
+    content: &str,
+    banner: &str,
+    footer: &str,
+    history: impl Fn(&[String]) -> String,
+) -> Result<Vec<OutdatedSection>> {
+    if !banner.contains("{templates}") {
+        return Err(Error::BannerWithoutTemplates);
+    }
+    let sections = generated_sections(content, banner, Some(footer));
+
+    let trim_blank = |lines: Vec<&str>| -> String {
+        let start = lines
+            .iter()
+            .position(|l| !l.is_empty())
+            .unwrap_or(lines.len());
+        let end = lines
+            .iter()
+            .rposition(|l| !l.is_empty())
+            .map_or(start, |i| i + 1);
+        lines[start..end]
+            .iter()
+            .map(|line| format!("{line}\n"))
+            .collect()
+    };
+
+    let mut outdated = Vec::new();
+    for (templates, lines) in sections {
+        let keys = section_keys(data, &templates);
+        let (expected, unknown) = get_templates(data, &keys, None, false, false)?;
+        let history = history(&templates);
+        let generated: HashSet<&str> = expected
+            .lines()
+            .chain(history.lines())
+            .map(trim_pattern)
+            .collect();
+        let expected = trim_blank(expected.lines().map(trim_pattern).collect());
+        let actual = trim_blank(
+            lines
+                .into_iter()
+                .filter(|line| line.is_empty() || generated.contains(line))
+                .collect(),
+        );
+        let name = templates.join(", ");
+        let diff = diff::unified_diff(
+            &actual,
+            &expected,
+            &format!("a/.gitignore ({name})"),
+            &format!("b/.gitignore ({name})"),
+        );
+        if diff.is_empty() && unknown.is_empty() {
+            continue;
+        }
+        outdated.push(OutdatedSection {
+            templates,
+            unknown,
+            diff,
+        });
+    }
+
+    Ok(outdated)
+}
+
+/// Returns the names, or alias targets, that `get_templates` would not be able
+/// to resolve to a user template, alias or template.
+pub fn unresolved_templates(data: &IgnoreData, names: &[String]) -> Vec<String> {
+    let mut unresolved = Vec::new();
+
+    for name in names {
+        if data.get_user_template(name).is_some() {
+            continue;
+        } else if data.get_alias(name).is_some() {
+            for target in data.expand_alias(name) {
+                if !target.resolved {
+                    unresolved.push(format!("{} (in alias {name})", target.key));
+                }
+            }
+        } else if !data.has_template(name) {
+            unresolved.push(name.clone());
+        }
+    }
+
+    unresolved
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{
+        IgnoreData, Type, civil_from_days, description, extend_last_banner, get_templates,
+        globals_last, synonym, with_namespace,
+    };
+    use crate::{cli::TemplateCase, error::Error};
+
+    #[test]
+    fn describes_templates_by_their_first_comment() {
+        assert_eq!(
+            description("\n# Generated by Cargo\n# will have compiled files\ndebug/\n"),
+            Some("Generated by Cargo".to_string())
+        );
+        assert_eq!(
+            description("#\n## Logs ##\nlogs\n"),
+            Some("Logs".to_string())
+        );
+        assert_eq!(description("target/\n# not a description\n"), None);
+    }
+
+    #[test]
+    fn moves_global_templates_last() {
+        let mut names = ["Global/macOS", "Rust", "global/vim", "Node"].map(String::from);
+        globals_last(&mut names);
+        assert_eq!(names, ["Rust", "Node", "Global/macOS", "global/vim"]);
+    }
+
+    #[test]
+    fn prefixes_names_with_the_namespace() {
+        assert_eq!(
+            with_namespace("Golang/Hugo", "community/").as_deref(),
+            Some("community/Golang/Hugo")
+        );
+        assert_eq!(with_namespace("Community/Golang/Hugo", "community"), None);
+    }
+
+    #[test]
+    fn resolves_synonyms_ignoring_case() {
+        assert_eq!(synonym("py"), Some("Python"));
+        assert_eq!(synonym("JS"), Some("Node"));
+        assert_eq!(synonym("Python"), None);
+    }
+
+    #[test]
+    fn converts_days_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19_722), (2023, 12, 31));
+        assert_eq!(civil_from_days(20_742), (2026, 10, 16));
+    }
+
+    #[test]
+    fn sorts_by_kind_then_key() {
+        let template = |key: &str| Type::Template {
+            key: key.to_string(),
+            path: PathBuf::new(),
+        };
+        let alias = |key: &str| Type::Alias {
+            key: key.to_string(),
+            aliases: Vec::new(),
+        };
+        let user_template = |key: &str| Type::UserTemplate {
+            key: key.to_string(),
+            content: String::new(),
+            extends: Vec::new(),
+        };
+        let mut data = [
+            user_template("docs"),
+            alias("stack"),
+            template("Rust"),
+            user_template("api"),
+            template("Global/macOS"),
+            alias("js"),
+            template("Node"),
+        ];
+        data.sort_unstable();
+        let keys: Vec<&str> = data.iter().map(Type::key).collect();
+        assert_eq!(
+            keys,
+            ["Global/macOS", "Node", "Rust", "js", "stack", "api", "docs"]
+        );
+    }
+
+    #[test]
+    fn fails_when_a_template_cant_be_read() {
+        let data = IgnoreData {
+            data: vec![Type::Template {
+                key: "Rust".to_string(),
+                path: PathBuf::from("does/not/exist/Rust.gitignore"),
+            }],
+            template_case: TemplateCase::Insensitive,
+        };
+        assert!(data.has_template("rust"));
+        assert!(matches!(
+            data.get_template("rust"),
+            Err(Error::TemplateUnreadable { key, .. }) if key == "Rust"
+        ));
+        assert!(get_templates(&data, &["Rust".to_string()], None, false, false).is_err());
+    }
+
+    #[test]
+    fn extends_the_last_banner() {
+        let banner = "# {date}: {templates} #";
+        let content = "# 2024-01-02: Rust #\ntarget/\n\n# 2024-03-04: Go #\r\n*.exe\n";
+        let names = ["Go".to_string(), "Node".to_string()];
+        assert_eq!(
+            extend_last_banner(content, banner, &names),
+            "# 2024-01-02: Rust #\ntarget/\n\n# 2024-03-04: Go, Node #\r\n*.exe\n"
+        );
+        assert_eq!(extend_last_banner("*.log\n", banner, &names), "*.log\n");
+    }
 }
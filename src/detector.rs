@@ -1,4 +1,4 @@
-use std::ffi::OsString;
+use std::{collections::HashMap, ffi::OsString};
 
 include!(concat!(env!("OUT_DIR"), "/detectors.rs"));
 
@@ -8,11 +8,54 @@ pub struct Detectors {
 }
 
 impl Detectors {
-    pub fn detects<E: DirEntry>(&self, entries: &[E]) -> Vec<String> {
-        self.detectors
+    /// Detects the templates for the given directory entries, reporting which
+    /// entries triggered each of them. The templates and their trigger files
+    /// are sorted, so the result doesn't depend on the order of `entries`,
+    /// which comes from the file system, or of the user rules.
+    pub fn detects<E: DirEntry>(&self, entries: &[E]) -> Vec<Detection> {
+        let mut detections: Vec<Detection> = self
+            .detectors
             .iter()
             .filter_map(|detector| detector.detects(entries))
-            .collect()
+            .collect();
+        for detection in &mut detections {
+            detection.trigger_files.sort_unstable();
+        }
+        detections.sort_unstable_by(|a, b| {
+            a.template
+                .to_lowercase()
+                .cmp(&b.template.to_lowercase())
+                .then_with(|| a.template.cmp(&b.template))
+        });
+        detections
+    }
+}
+
+#[derive(Debug)]
+pub struct Detection {
+    pub template: String,
+    pub trigger_files: Vec<OsString>,
+}
+
+impl Detectors {
+    /// Extends the built-in detectors with user defined rules, mapping a file
+    /// or folder name pattern (`*` and `?` wildcards are supported) to a
+    /// template. Rules for an already known template are merged into it. The
+    /// rules are added sorted by pattern, as the order of `rules` is random.
+    pub fn with_user_rules(mut self, rules: &HashMap<String, String>) -> Self {
+        let mut rules: Vec<_> = rules.iter().collect();
+        rules.sort_unstable();
+        for (pattern, template) in rules {
+            let matcher = Matcher::Pattern(pattern.clone());
+            match self.detectors.iter_mut().find(|d| &d.template == template) {
+                Some(detector) => detector.matchers.push(matcher),
+                None => self.detectors.push(Detector {
+                    template: template.clone(),
+                    matchers: vec![matcher],
+                }),
+            }
+        }
+        self
     }
 }
 
@@ -31,15 +74,19 @@ struct Detector {
 }
 
 impl Detector {
-    fn detects<E: DirEntry>(&self, entries: &[E]) -> Option<String> {
-        let result = self
-            .matchers
+    fn detects<E: DirEntry>(&self, entries: &[E]) -> Option<Detection> {
+        let trigger_files: Vec<OsString> = entries
             .iter()
-            .any(|matcher| entries.iter().any(|entry| matcher.matches(entry)));
-        if result {
-            Some(self.template.clone())
-        } else {
+            .filter(|entry| self.matchers.iter().any(|matcher| matcher.matches(*entry)))
+            .map(|entry| entry.name())
+            .collect();
+        if trigger_files.is_empty() {
             None
+        } else {
+            Some(Detection {
+                template: self.template.clone(),
+                trigger_files,
+            })
         }
     }
 }
@@ -77,6 +124,7 @@ enum Matcher {
     FileExtension(OsString),
     FileName(OsString),
     DirName(OsString),
+    Pattern(String),
 }
 
 impl Matcher {
@@ -87,15 +135,43 @@ impl Matcher {
                 entry.is_file() && entry.extension() == Some(extension.clone())
             }
             Self::DirName(name) => entry.is_dir() && &entry.name() == name,
+            Self::Pattern(pattern) => wildcard_match(pattern, &entry.name().to_string_lossy()),
         }
     }
 }
 
+/// Matches `text` against a simple wildcard `pattern`, where `*` matches any
+/// run of characters and `?` matches a single character.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 #[cfg(test)]
 mod tests {
-    use std::ffi::OsString;
+    use std::{collections::HashMap, ffi::OsString};
 
-    use crate::detector::{Detectors, DirEntry};
+    use crate::detector::{Detection, Detectors, DirEntry, wildcard_match};
 
     struct FakeDirEntry {
         file_name: OsString,
@@ -138,10 +214,17 @@ mod tests {
         }
     }
 
+    fn templates(detections: Vec<Detection>) -> Vec<String> {
+        detections
+            .into_iter()
+            .map(|detection| detection.template)
+            .collect()
+    }
+
     #[test]
     fn detects_java_from_build_gradle() {
         let entry = FakeDirEntry::new("build.gradle", Some("gradle"), true, false);
-        let result = Detectors::default().detects(&Vec::from([entry]));
+        let result = templates(Detectors::default().detects(&Vec::from([entry])));
         assert!(result.contains(&"gradle".to_string()));
         assert!(result.contains(&"java".to_string()));
         assert_eq!(result.len(), 2);
@@ -150,63 +233,157 @@ mod tests {
     #[test]
     fn detects_java_from_pom_xml() {
         let entry = FakeDirEntry::new("pom.xml", Some("xml"), true, false);
-        let result = Detectors::default().detects(&Vec::from([entry]));
+        let result = templates(Detectors::default().detects(&Vec::from([entry])));
         assert_eq!(result, vec!["java"])
     }
 
     #[test]
     fn detects_node_from_package_json() {
         let entry = FakeDirEntry::new("package.json", Some("json"), true, false);
-        let result = Detectors::default().detects(&Vec::from([entry]));
+        let result = templates(Detectors::default().detects(&Vec::from([entry])));
         assert_eq!(result, vec!["node"])
     }
 
     #[test]
     fn detects_python_from_requirements_txt() {
         let entry = FakeDirEntry::new("requirements.txt", Some("txt"), true, false);
-        let result = Detectors::default().detects(&Vec::from([entry]));
+        let result = templates(Detectors::default().detects(&Vec::from([entry])));
         assert_eq!(result, vec!["python"])
     }
 
     #[test]
     fn detects_haskell_from_dot_cabal() {
         let entry = FakeDirEntry::new("git-ignore.cabal", Some("cabal"), true, false);
-        let result = Detectors::default().detects(&Vec::from([entry]));
+        let result = templates(Detectors::default().detects(&Vec::from([entry])));
         assert_eq!(result, vec!["haskell"])
     }
 
     #[test]
     fn detects_haskell_from_stack_yaml() {
         let entry = FakeDirEntry::new("stack.yaml", Some("yaml"), true, false);
-        let result = Detectors::default().detects(&Vec::from([entry]));
+        let result = templates(Detectors::default().detects(&Vec::from([entry])));
         assert_eq!(result, vec!["haskell"])
     }
 
     #[test]
     fn detects_php_from_compose_json() {
         let entry = FakeDirEntry::new("composer.json", Some("json"), true, false);
-        let result = Detectors::default().detects(&Vec::from([entry]));
+        let result = templates(Detectors::default().detects(&Vec::from([entry])));
         assert_eq!(result, vec!["composer"])
     }
 
     #[test]
     fn detects_ruby_from_gemfile() {
         let entry = FakeDirEntry::new("Gemfile", None, true, false);
-        let result = Detectors::default().detects(&Vec::from([entry]));
+        let result = templates(Detectors::default().detects(&Vec::from([entry])));
         assert_eq!(result, vec!["ruby"])
     }
 
     #[test]
     fn detects_rust_from_cargo_toml() {
         let entry = FakeDirEntry::new("Cargo.toml", Some("toml"), true, false);
-        let result = Detectors::default().detects(&Vec::from([entry]));
+        let result = templates(Detectors::default().detects(&Vec::from([entry])));
         assert_eq!(result, vec!["rust"])
     }
 
+    #[test]
+    fn detects_in_stable_order() {
+        let rules = HashMap::from([
+            ("*.sln".to_string(), "VisualStudio".to_string()),
+            ("*.unity".to_string(), "Unity".to_string()),
+        ]);
+        let mut entries = Vec::from([
+            FakeDirEntry::new("project.sln", Some("sln"), true, false),
+            FakeDirEntry::new("package.json", Some("json"), true, false),
+            FakeDirEntry::new("main.unity", Some("unity"), true, false),
+            FakeDirEntry::new("Cargo.toml", Some("toml"), true, false),
+            FakeDirEntry::new("other.sln", Some("sln"), true, false),
+        ]);
+        let detectors = Detectors::default().with_user_rules(&rules);
+        let expected = vec!["node", "rust", "Unity", "VisualStudio"];
+        assert_eq!(templates(detectors.detects(&entries)), expected);
+
+        entries.reverse();
+        let detections = detectors.detects(&entries);
+        assert_eq!(
+            detections[3].trigger_files,
+            vec![OsString::from("other.sln"), OsString::from("project.sln")]
+        );
+        assert_eq!(templates(detections), expected);
+    }
+
+    #[test]
+    fn reports_trigger_files() {
+        let entries = Vec::from([
+            FakeDirEntry::new("Cargo.toml", Some("toml"), true, false),
+            FakeDirEntry::new("README.md", Some("md"), true, false),
+        ]);
+        let detections = Detectors::default().detects(&entries);
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].template, "rust");
+        assert_eq!(
+            detections[0].trigger_files,
+            vec![OsString::from("Cargo.toml")]
+        );
+    }
+
     #[test]
     fn detects_scala_from_folder() {
         let entry = FakeDirEntry::new(".metals", None, false, true);
-        let result = Detectors::default().detects(&Vec::from([entry]));
+        let result = templates(Detectors::default().detects(&Vec::from([entry])));
         assert_eq!(result, vec!["scala"])
     }
+
+    #[test]
+    fn detects_from_user_rule() {
+        let rules = HashMap::from([("*.sln".to_string(), "VisualStudio".to_string())]);
+        let entry = FakeDirEntry::new("project.sln", Some("sln"), true, false);
+        let result = templates(
+            Detectors::default()
+                .with_user_rules(&rules)
+                .detects(&Vec::from([entry])),
+        );
+        assert_eq!(result, vec!["VisualStudio"])
+    }
+
+    #[test]
+    fn adds_user_rules_sorted_by_pattern() {
+        let rules = HashMap::from([
+            ("*.unity".to_string(), "Unity".to_string()),
+            ("*.sln".to_string(), "VisualStudio".to_string()),
+            ("*.csproj".to_string(), "VisualStudio".to_string()),
+            ("*.pro".to_string(), "Qt".to_string()),
+        ]);
+        let detectors = Detectors::default().with_user_rules(&rules);
+        let added: Vec<&str> = detectors.detectors[Detectors::default().detectors.len()..]
+            .iter()
+            .map(|detector| detector.template.as_str())
+            .collect();
+        assert_eq!(added, vec!["VisualStudio", "Qt", "Unity"]);
+    }
+
+    #[test]
+    fn user_rule_merges_into_builtin_template() {
+        let rules = HashMap::from([("*.rs".to_string(), "rust".to_string())]);
+        let entries = Vec::from([
+            FakeDirEntry::new("Cargo.toml", Some("toml"), true, false),
+            FakeDirEntry::new("main.rs", Some("rs"), true, false),
+        ]);
+        let result = templates(
+            Detectors::default()
+                .with_user_rules(&rules)
+                .detects(&entries),
+        );
+        assert_eq!(result, vec!["rust"])
+    }
+
+    #[test]
+    fn wildcard_matching() {
+        assert!(wildcard_match("pom.xml", "pom.xml"));
+        assert!(wildcard_match("*.sln", "app.sln"));
+        assert!(wildcard_match("build.*", "build.gradle"));
+        assert!(wildcard_match("?ar*", "Cargo.toml"));
+        assert!(wildcard_match("*a*o*", "Cargo.toml"));
+        assert!(!wildcard_match("*.sln", "app.slnx"));
+    }
 }
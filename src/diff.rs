@@ -0,0 +1,172 @@
+use std::fmt::Write;
+
+/// The number of unchanged lines shown around each change.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Computes the line operations turning `old` into `new`, based on their
+/// longest common subsequence.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Op<'a>> {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..].
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            ops.push(Op::Equal(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push(Op::Delete(old[i]));
+            i += 1;
+        } else {
+            ops.push(Op::Insert(new[j]));
+            j += 1;
+        }
+    }
+    ops.extend(old[i..].iter().map(|line| Op::Delete(line)));
+    ops.extend(new[j..].iter().map(|line| Op::Insert(line)));
+    ops
+}
+
+/// Formats a hunk range, `start` is the 0-based index of its first line.
+fn range(start: usize, len: usize) -> String {
+    match len {
+        // An empty range refers to the line before it.
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+/// Renders a `diff -u` style diff between `old` and `new`, labelled with
+/// `old_name` and `new_name`. Returns an empty string if they're equal.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Equal(_)))
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Group the changes into hunks, merging those whose context overlaps.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &change in &changes {
+        let start = change.saturating_sub(CONTEXT);
+        let end = (change + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    for (start, end) in hunks {
+        // The old and new line numbers at the start of the hunk.
+        let old_start = ops[..start]
+            .iter()
+            .filter(|op| !matches!(op, Op::Insert(_)))
+            .count();
+        let new_start = ops[..start]
+            .iter()
+            .filter(|op| !matches!(op, Op::Delete(_)))
+            .count();
+        let hunk = &ops[start..end];
+        let old_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Insert(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Delete(_)))
+            .count();
+
+        writeln!(
+            out,
+            "@@ -{} +{} @@",
+            range(old_start, old_len),
+            range(new_start, new_len)
+        )
+        .unwrap();
+        for op in hunk {
+            match op {
+                Op::Equal(line) => writeln!(out, " {line}").unwrap(),
+                Op::Delete(line) => writeln!(out, "-{line}").unwrap(),
+                Op::Insert(line) => writeln!(out, "+{line}").unwrap(),
+            }
+        }
+    }
+    out
+}
+
+/// Diffs the `current` content of `.gitignore`, `None` if it doesn't exist yet,
+/// against the `new` content.
+pub fn gitignore_diff(current: Option<&str>, new: &str) -> String {
+    let old_name = if current.is_some() {
+        "a/.gitignore"
+    } else {
+        "/dev/null"
+    };
+    unified_diff(current.unwrap_or_default(), new, old_name, "b/.gitignore")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unified_diff;
+
+    #[test]
+    fn equal_content_has_no_diff() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "a", "b"), "");
+    }
+
+    #[test]
+    fn diffs_new_file() {
+        assert_eq!(
+            unified_diff("", "target/\n", "/dev/null", "b/.gitignore"),
+            "--- /dev/null\n+++ b/.gitignore\n@@ -0,0 +1 @@\n+target/\n"
+        );
+    }
+
+    #[test]
+    fn diffs_appended_lines_with_context() {
+        let old = "1\n2\n3\n4\n5\n";
+        let new = "1\n2\n3\n4\n5\n\n# Rust\ntarget/\n";
+        assert_eq!(
+            unified_diff(old, new, "a/.gitignore", "b/.gitignore"),
+            "--- a/.gitignore\n+++ b/.gitignore\n@@ -3,3 +3,6 @@\n 3\n 4\n 5\n+\n+# Rust\n+target/\n"
+        );
+    }
+
+    #[test]
+    fn splits_distant_changes_into_hunks() {
+        let old = "a\n1\n2\n3\n4\n5\n6\n7\n8\nb\n";
+        let new = "A\n1\n2\n3\n4\n5\n6\n7\n8\nB\n";
+        let diff = unified_diff(old, new, "a", "b");
+        assert_eq!(diff.matches("@@ -").count(), 2);
+        assert!(diff.contains("@@ -1,4 +1,4 @@\n-a\n+A\n 1\n"));
+        assert!(diff.contains("@@ -7,4 +7,4 @@\n 6\n 7\n 8\n-b\n+B\n"));
+    }
+}
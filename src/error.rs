@@ -0,0 +1,124 @@
+use std::{io, path::PathBuf, time::Duration};
+
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The failures of the core functionality, so they can be told apart instead
+/// of only being reported. `main` turns them into an `anyhow::Error`.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Reading or writing a file or directory failed, `context` says which.
+    #[error("{context}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+    /// `git` couldn't be started.
+    #[error("Failed to execute '{command}'")]
+    Git {
+        command: String,
+        #[source]
+        source: io::Error,
+    },
+    /// `git` ran, but exited unsuccessfully.
+    #[error("'{command}' failed: {stderr}")]
+    GitFailed { command: String, stderr: String },
+    /// `git` was killed because it didn't finish in time.
+    #[error("'{command}' timed out after {timeout:?}")]
+    GitTimeout { command: String, timeout: Duration },
+    /// `--no-wait` skipped the update while another process was creating the cache.
+    #[error(
+        "Another process is still creating the cache at {}, run again without --no-wait to wait for it",
+        .0.display()
+    )]
+    CacheBusy(PathBuf),
+    /// A download failed or returned an unsuccessful status.
+    #[error("Failed to download {url}: {reason}")]
+    Network { url: String, reason: String },
+    /// A template from the cache couldn't be read when it was used.
+    #[error("Failed to read template {key} from {}", .path.display())]
+    TemplateUnreadable {
+        key: String,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("No template, alias or user template named {0} found")]
+    TemplateNotFound(String),
+    #[error("No alias named {0} found, pass --create to create it")]
+    AliasNotFound(String),
+    #[error("Could not resolve template(s), nothing was written: {}", .0.join(", "))]
+    UnresolvedTemplates(Vec<String>),
+    /// Globs and aliases resolved to more templates than allowed, which is
+    /// most likely a mistake.
+    #[error(
+        "Expansion produced {count} templates, exceeding the limit of {limit}, pass --limit-templates to raise it"
+    )]
+    TooManyTemplates { count: usize, limit: usize },
+    #[error(
+        "{} doesn't look like a checkout of github/gitignore, it has no Global directory",
+        .0.display()
+    )]
+    NotAGitignoreRepo(PathBuf),
+    /// `--fail-on-empty` was passed and the templates added nothing.
+    #[error("The templates added no new lines")]
+    NothingAdded,
+    /// The `.gitignore` can't or shouldn't be written to.
+    #[error("{}{reason}", .path.display())]
+    NotWritable { path: PathBuf, reason: String },
+    #[error("{context}")]
+    InvalidConfig {
+        context: String,
+        #[source]
+        source: toml::de::Error,
+    },
+    /// `config validate` found problems, they have been reported already.
+    #[error("Found {0} problem(s) in the config")]
+    ConfigProblems(usize),
+    #[error(
+        "No template directory is configured, set template_dir in the config or pass --template-dir"
+    )]
+    NoTemplateDir,
+    /// A template read with `--from-vendored` isn't listed in the manifest.
+    #[error("{key} isn't listed in {}, vendor it with --vendor first", .manifest.display())]
+    NotVendored { key: String, manifest: PathBuf },
+    #[error("Failed to parse {}", .0.display())]
+    InvalidManifest(PathBuf, #[source] toml::de::Error),
+    #[error("Failed to serialize {0}")]
+    Serialize(&'static str, #[source] toml::ser::Error),
+    #[error("The banner has no {{templates}} placeholder to find the templates by")]
+    BannerWithoutTemplates,
+}
+
+/// Describes what an I/O operation was doing when it failed, like
+/// `anyhow::Context`.
+pub trait IoContext<T> {
+    fn io_context(self, context: impl FnOnce() -> String) -> Result<T>;
+}
+
+impl<T> IoContext<T> for io::Result<T> {
+    fn io_context(self, context: impl FnOnce() -> String) -> Result<T> {
+        self.map_err(|source| Error::Io {
+            context: context(),
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn io_context_keeps_the_source() {
+        let err = Err::<(), _>(io::Error::from(io::ErrorKind::NotFound))
+            .io_context(|| "Failed to read foo".to_string())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Failed to read foo");
+        assert!(err.source().is_some());
+    }
+}
@@ -1,25 +1,53 @@
 use std::{
     collections::HashSet,
     env::current_dir,
-    fs::{DirEntry, File, OpenOptions, read_dir, read_to_string},
-    io::{self, Write as IoWrite}, // Renamed to avoid conflict
-    path::Path,
-    process::Command, // Added for running git commands
-    sync::LazyLock,
+    fmt,
+    fs::{DirEntry, File, TryLockError, read_dir, read_to_string},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio}, // Added for running git commands
+    sync::{LazyLock, OnceLock},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{Context, Result}; // Added Context
+use attohttpc::{ProxySettings, RequestBuilder, StatusCode};
 use colored::Colorize;
-use etcetera::{AppStrategyArgs, choose_app_strategy};
+use etcetera::{AppStrategy, AppStrategyArgs, choose_app_strategy};
+use url::Url;
 
 use crate::{
-    data::{CACHE_DIR, GIT_REPO_CACHE_DIR}, // Use GIT_REPO_CACHE_DIR, remove CACHE_FILE
-    detector::Detectors,
+    cli::{MergeStrategy, OutputEncoding, OutputFormat, Source, TemplateCase},
+    data::{self, CACHE_DIR, GIT_REPO_DIR_NAME},
+    detector::{Detection, Detectors},
+    diff,
+    error::{Error, IoContext, Result},
+    merge::{self, trim_pattern},
+    user_data::UserData,
 };
 
 const GITHUB_GITIGNORE_BASE_URL: &str = "https://raw.githubusercontent.com/github/gitignore/main/";
+const GITIGNORE_IO_API_URL: &str = "https://www.toptal.com/developers/gitignore/api/";
 const GITHUB_GITIGNORE_REPO_URL: &str = "https://github.com/github/gitignore.git";
 const GITIGNORE_FILE_NAME: &str = ".gitignore";
+const UPDATE_LOCK_FILE_NAME: &str = "update.lock";
+/// Touched after every successful update, its modification time is the age of the cache.
+const LAST_UPDATE_FILE_NAME: &str = "last_update";
+/// How many days old the cache can get before a warning is shown, unless
+/// configured otherwise.
+pub const DEFAULT_CACHE_MAX_AGE_DAYS: u64 = 30;
+/// How long responses from direct fetches are served from the cache.
+const HTTP_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The proxy for the whole run, set from `--proxy`. Without it, proxies are
+/// taken from the environment, see [`http_get`].
+pub static PROXY_OVERRIDE: OnceLock<Url> = OnceLock::new();
+
+static HTTP_CACHE_DIR: LazyLock<PathBuf> = LazyLock::new(|| CACHE_DIR.join("http"));
+/// Holds the lock files serializing writes to a `.gitignore`, see [`lock_gitignore`].
+/// It's in the user's own cache directory, also with `--cache-dir`, so every run
+/// of theirs finds the same lock files.
+static WRITE_LOCK_DIR: LazyLock<PathBuf> = LazyLock::new(|| PROJECT_DIRS.cache_dir().join("locks"));
 
 #[cfg(target_os = "windows")]
 pub static PROJECT_DIRS: LazyLock<etcetera::app_strategy::Windows> = LazyLock::new(|| {
@@ -45,49 +73,127 @@ pub static PROJECT_DIRS: LazyLock<etcetera::app_strategy::Xdg> = LazyLock::new(|
 pub struct Core {
     // server field removed
     detectors: Detectors,
+    cache_dir: PathBuf,
+}
+
+impl Default for Core {
+    /// Uses the built-in detectors and the default, or `--cache-dir`, cache directory.
+    fn default() -> Self {
+        Core::with_cache_dir(CACHE_DIR.clone(), Detectors::default())
+    }
 }
 
 impl Core {
     /// Creates a new instance of the `git-ignore` program.
     /// Caching uses a local clone of the github/gitignore repository.
-    pub fn new() -> Self {
+    /// The built-in detectors are extended with the user's detection rules.
+    pub fn new(user_data: &UserData) -> Self {
+        Core::with_cache_dir(
+            CACHE_DIR.clone(),
+            Detectors::default().with_user_rules(&user_data.detectors),
+        )
+    }
+
+    /// Creates an instance that keeps its cache in `cache_dir` and autodetects
+    /// templates with `detectors`, e.g. to run against a fixture repository.
+    pub fn with_cache_dir(cache_dir: PathBuf, detectors: Detectors) -> Self {
         Core {
-            // server initialization removed
-            detectors: Detectors::default(),
+            detectors,
+            cache_dir,
         }
     }
 
+    fn repo_dir(&self) -> PathBuf {
+        self.cache_dir.join(GIT_REPO_DIR_NAME)
+    }
+
     /// Updates the local cache of the github/gitignore repository.
     /// Clones the repository if it doesn't exist, or pulls the latest changes if it does.
     /// Requires `git` to be installed and in PATH.
-    pub fn update(&self) -> Result<()> {
-        // Ensure the base cache directory exists. GIT_REPO_CACHE_DIR will be created by git clone.
-        if !CACHE_DIR.exists() {
-            std::fs::create_dir_all(CACHE_DIR.as_path())
-                .with_context(|| format!("Failed to create cache directory at {:?}", CACHE_DIR.as_path()))?;
-            eprintln!("{}: Created cache directory at {}", "Info".bold().green(), CACHE_DIR.display());
+    ///
+    /// Concurrent updates are serialized with a lock file in the cache directory,
+    /// if `no_wait` is set the update is skipped when another process holds it.
+    /// That is an error if the cache was never updated successfully, as it may
+    /// be missing or only partly cloned.
+    /// Unless `quiet` is set, the progress of the initial clone is shown. The
+    /// git process is killed if it takes longer than `timeout`.
+    pub fn update(&self, no_wait: bool, quiet: bool, timeout: Duration) -> Result<()> {
+        let repo_dir = self.repo_dir();
+        // Ensure the base cache directory exists. The repository dir will be created by git clone.
+        if !self.cache_dir.exists() {
+            std::fs::create_dir_all(self.cache_dir.as_path()).io_context(|| {
+                format!(
+                    "Failed to create cache directory at {:?}",
+                    self.cache_dir.as_path()
+                )
+            })?;
+            eprintln!(
+                "{}: Created cache directory at {}",
+                "Info".bold().green(),
+                self.cache_dir.display()
+            );
+        }
+
+        // The lock is released when `lock_file` is dropped at the end of the update.
+        let lock_path = self.cache_dir.join(UPDATE_LOCK_FILE_NAME);
+        let lock_file = File::create(&lock_path)
+            .io_context(|| format!("Failed to create lock file at {:?}", lock_path))?;
+        match lock_file.try_lock() {
+            Ok(()) => {}
+            // The other process may still be cloning, then there is no cache to use yet.
+            Err(TryLockError::WouldBlock) if no_wait && self.last_update().is_none() => {
+                return Err(Error::CacheBusy(repo_dir));
+            }
+            Err(TryLockError::WouldBlock) if no_wait => {
+                eprintln!(
+                    "{}: Another process is already updating the local gitignore repository cache, skipping update.",
+                    "Info".bold().green()
+                );
+                return Ok(());
+            }
+            Err(TryLockError::WouldBlock) => {
+                eprintln!(
+                    "{}: Waiting for another process to finish updating the local gitignore repository cache...",
+                    "Info".bold().green()
+                );
+                lock_file
+                    .lock()
+                    .io_context(|| format!("Failed to lock {:?}", lock_path))?;
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(Error::Io {
+                    context: format!("Failed to lock {:?}", lock_path),
+                    source: e,
+                });
+            }
         }
 
-        if GIT_REPO_CACHE_DIR.exists() {
+        if repo_dir.exists() {
             eprintln!(
                 "{}: Attempting to update existing local gitignore repository cache at {}...",
                 "Info".bold().green(),
-                GIT_REPO_CACHE_DIR.display()
+                repo_dir.display()
             );
-            let output = Command::new("git")
+            let mut command = git_command();
+            command
                 .arg("-C")
-                .arg(GIT_REPO_CACHE_DIR.as_path())
+                .arg(repo_dir.as_path())
                 .arg("pull")
-                .output()
-                .with_context(|| format!("Failed to execute 'git pull' in {:?}", GIT_REPO_CACHE_DIR.as_path()))?;
+                .stderr(Stdio::piped());
+            let output = output_with_timeout(&mut command, "git pull", timeout)?;
 
             if output.status.success() {
                 eprintln!(
                     "{}: Successfully updated local gitignore repository.",
                     "Info".bold().green()
                 );
+                self.record_update()?;
+                self.report_template_count();
                 if !output.stdout.is_empty() {
-                    eprintln!("Git pull output:\n{}", String::from_utf8_lossy(&output.stdout));
+                    eprintln!(
+                        "Git pull output:\n{}",
+                        String::from_utf8_lossy(&output.stdout)
+                    );
                 }
             } else {
                 eprintln!(
@@ -96,7 +202,10 @@ impl Core {
                     output.status
                 );
                 if !output.stderr.is_empty() {
-                    eprintln!("Git pull error:\n{}", String::from_utf8_lossy(&output.stderr));
+                    eprintln!(
+                        "Git pull error:\n{}",
+                        String::from_utf8_lossy(&output.stderr)
+                    );
                 }
                 // Optionally, could suggest deleting the cache dir and retrying.
             }
@@ -105,20 +214,32 @@ impl Core {
                 "{}: Local gitignore repository cache not found. Cloning from {} to {}...",
                 "Info".bold().green(),
                 GITHUB_GITIGNORE_REPO_URL,
-                GIT_REPO_CACHE_DIR.display()
+                repo_dir.display()
             );
-            let output = Command::new("git")
-                .arg("clone")
+            let mut command = git_command();
+            command.arg("clone");
+            if quiet {
+                command.arg("--quiet").stderr(Stdio::piped());
+            } else {
+                // Stream git's progress straight to the terminal, as the first clone can take a while.
+                command.arg("--progress").stderr(Stdio::inherit());
+            }
+            command
                 .arg(GITHUB_GITIGNORE_REPO_URL)
-                .arg(GIT_REPO_CACHE_DIR.as_path())
-                .output()
-                .with_context(|| format!("Failed to execute 'git clone {}'", GITHUB_GITIGNORE_REPO_URL))?;
+                .arg(repo_dir.as_path());
+            let output = output_with_timeout(&mut command, "git clone", timeout);
+            if !output.as_ref().is_ok_and(|output| output.status.success()) {
+                self.remove_failed_clone();
+            }
+            let output = output?;
 
             if output.status.success() {
                 eprintln!(
                     "{}: Successfully cloned gitignore repository.",
                     "Info".bold().green()
                 );
+                self.record_update()?;
+                self.report_template_count();
             } else {
                 eprintln!(
                     "{}: Failed to clone gitignore repository. 'git clone' exited with status: {}",
@@ -126,7 +247,10 @@ impl Core {
                     output.status
                 );
                 if !output.stderr.is_empty() {
-                    eprintln!("Git clone error:\n{}", String::from_utf8_lossy(&output.stderr));
+                    eprintln!(
+                        "Git clone error:\n{}",
+                        String::from_utf8_lossy(&output.stderr)
+                    );
                 }
                 // Optionally, could suggest checking git installation or network.
             }
@@ -137,34 +261,440 @@ impl Core {
     /// Autodetects templates based on files in the current directory.
     /// This uses the locally cached github/gitignore repository.
     pub fn autodetect_templates(&self) -> Result<Vec<String>> {
-        let entries: Vec<DirEntry> = read_dir(current_dir()?)?.map(Result::unwrap).collect();
+        let dir = current_dir().io_context(|| "Failed to get the current directory".to_string())?;
+        self.autodetect_templates_in(&dir)
+    }
+
+    /// Autodetects templates based on the files in `dir`, like
+    /// [`Core::autodetect_templates`] does for the current directory.
+    pub fn autodetect_templates_in(&self, dir: &Path) -> Result<Vec<String>> {
+        let entries = dir_entries(dir)?;
+        Ok(self
+            .detectors
+            .detects(entries.as_slice())
+            .into_iter()
+            .map(|detection| detection.template)
+            .collect())
+    }
+
+    /// Runs autodetection in the current directory, keeping track of which
+    /// files triggered each template.
+    pub fn autodetect(&self) -> Result<Vec<Detection>> {
+        let entries = current_dir_entries()?;
         Ok(self.detectors.detects(entries.as_slice()))
     }
 
     // fetch_gitignore method removed as it's no longer used.
+
+    pub fn cache_exists(&self) -> bool {
+        // Now checks for the existence of the git repository cache directory
+        let repo_dir = self.repo_dir();
+        repo_dir.exists() && repo_dir.is_dir()
+    }
+
+    /// Removes what a failed or killed `git clone` left behind, as it would be
+    /// taken for the cache from then on, see [`Core::cache_exists`].
+    fn remove_failed_clone(&self) {
+        let repo_dir = self.repo_dir();
+        if repo_dir.exists()
+            && let Err(e) = std::fs::remove_dir_all(&repo_dir)
+        {
+            eprintln!(
+                "{}: Failed to remove the incomplete clone at {}: {e}",
+                "Warning".bold().yellow(),
+                repo_dir.display()
+            );
+        }
+    }
+
+    fn record_update(&self) -> Result<()> {
+        let path = self.cache_dir.join(LAST_UPDATE_FILE_NAME);
+        File::create(&path).io_context(|| format!("Failed to record update time in {:?}", path))?;
+        Ok(())
+    }
+
+    /// Counts the templates in the cache like [`data::cache_templates`] reads
+    /// them, returns `(total, global)` where the total includes the ones in
+    /// `Global/`.
+    fn template_count(&self) -> (usize, usize) {
+        data::cache_templates(&self.repo_dir()).map_or((0, 0), |templates| {
+            let global = templates
+                .iter()
+                .filter(|template| data::is_global(template.key()))
+                .count();
+            (templates.len(), global)
+        })
+    }
+
+    /// Prints how many templates the cache contains after an update, warning if
+    /// it's empty.
+    fn report_template_count(&self) {
+        let (total, global) = self.template_count();
+        if total == 0 {
+            eprintln!(
+                "{}: Cache at {} contains no templates.",
+                "Warning".bold().yellow(),
+                self.repo_dir().display()
+            );
+        } else {
+            eprintln!(
+                "{}: Cache contains {} templates ({} global).",
+                "Info".bold().green(),
+                total,
+                global
+            );
+        }
+    }
+
+    /// The commit the cached github/gitignore clone is at, `None` if it isn't a
+    /// git repository.
+    pub fn cache_commit(&self) -> Option<String> {
+        self.git_in_cache(&["rev-parse", "HEAD"])
+    }
+
+    /// The abbreviated hash and the date of the commit the cache is at, like
+    /// `("abc1234", "2024-05-01")`, `None` if it isn't a git repository.
+    pub fn cache_commit_summary(&self) -> Option<(String, String)> {
+        let summary = self.git_in_cache(&["log", "-1", "--format=%h %cs"])?;
+        let (hash, date) = summary.split_once(' ')?;
+        Some((hash.to_string(), date.to_string()))
+    }
+
+    /// Runs `git` with `args` in the cached clone and returns its trimmed
+    /// stdout, `None` if the cache isn't a git repository or `git` failed.
+    fn git_in_cache(&self, args: &[&str]) -> Option<String> {
+        let repo_dir = self.repo_dir();
+        if !repo_dir.join(".git").exists() {
+            return None;
+        }
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&repo_dir)
+            .args(args)
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Every line the templates `names` had at some point in the history of
+    /// the cached clone, so `replace` can tell the lines upstream removed from
+    /// them apart from lines added by hand. Empty if the cache isn't a git
+    /// repository.
+    pub fn template_history(&self, names: &[String]) -> String {
+        if names.is_empty() {
+            return String::new();
+        }
+        let mut args = ["log", "--format=", "--patch", "--unified=0", "--"]
+            .map(String::from)
+            .to_vec();
+        for name in names {
+            // Banners name templates the way they were asked for.
+            args.push(format!(":(icase){name}.gitignore"));
+            args.push(format!(":(icase)Global/{name}.gitignore"));
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let Some(log) = self.git_in_cache(&args) else {
+            return String::new();
+        };
+        log.lines()
+            .filter(|line| !line.starts_with("+++ ") && !line.starts_with("--- "))
+            .filter_map(|line| line.strip_prefix(['+', '-']))
+            .map(|line| format!("{line}\n"))
+            .collect()
+    }
+
+    /// Fetches from upstream without touching the working tree and prints how
+    /// many commits the cache is behind, so staleness can be checked cheaply.
+    pub fn check_updates(&self, timeout: Duration) -> Result<()> {
+        let repo_dir = self.repo_dir();
+        if !repo_dir.join(".git").exists() {
+            eprintln!(
+                "{}: No github/gitignore cache found at {}, pass '-u' to create it.",
+                "Warning".bold().yellow(),
+                repo_dir.display()
+            );
+            return Ok(());
+        }
+
+        let run = |args: &[&str], name: &str| -> Result<String> {
+            let mut command = git_command();
+            command
+                .arg("-C")
+                .arg(&repo_dir)
+                .args(args)
+                .stderr(Stdio::piped());
+            let output = output_with_timeout(&mut command, name, timeout)?;
+            if !output.status.success() {
+                return Err(Error::GitFailed {
+                    command: name.to_string(),
+                    stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                });
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        };
+        run(&["fetch", "--quiet"], "git fetch")?;
+        let behind = run(&["rev-list", "--count", "HEAD..@{u}"], "git rev-list")?;
+        match behind.as_str() {
+            "0" => println!("github/gitignore cache is up to date."),
+            behind => println!(
+                "github/gitignore cache is {} commit(s) behind, pass '-u' to update it.",
+                behind.yellow()
+            ),
+        }
+        Ok(())
+    }
+
+    /// Prints the commit the cache is at, for reproducible bug reports.
+    pub fn print_cache_commit(&self) {
+        match self.cache_commit_summary() {
+            Some((hash, date)) => println!(
+                "github/gitignore cache at {} is at commit {} ({})",
+                self.repo_dir().display(),
+                hash.yellow(),
+                date
+            ),
+            None if self.cache_exists() => eprintln!(
+                "{}: Cache at {} is not a git repository, its commit is unknown.",
+                "Warning".bold().yellow(),
+                self.repo_dir().display()
+            ),
+            None => eprintln!(
+                "{}: No github/gitignore cache found at {}, pass '-u' to create it.",
+                "Warning".bold().yellow(),
+                self.repo_dir().display()
+            ),
+        }
+    }
+
+    /// When the cache was last updated, `None` if it was never updated since
+    /// the update time started being recorded.
+    fn last_update(&self) -> Option<SystemTime> {
+        self.cache_dir
+            .join(LAST_UPDATE_FILE_NAME)
+            .metadata()
+            .ok()?
+            .modified()
+            .ok()
+    }
+
+    /// How long ago the cache was last updated. Caches updated before the
+    /// update time was recorded fall back to when git last touched `.git`,
+    /// `None` if that's missing as well.
+    pub fn cache_age(&self) -> Option<Duration> {
+        self.last_update()
+            .or_else(|| {
+                self.repo_dir()
+                    .join(".git")
+                    .metadata()
+                    .ok()?
+                    .modified()
+                    .ok()
+            })?
+            .elapsed()
+            .ok()
+    }
+}
+
+fn current_dir_entries() -> Result<Vec<DirEntry>> {
+    let dir = current_dir().io_context(|| "Failed to get the current directory".to_string())?;
+    dir_entries(&dir)
 }
 
-pub fn cache_exists() -> bool {
-    // Now checks for the existence of the git repository cache directory
-    GIT_REPO_CACHE_DIR.exists() && GIT_REPO_CACHE_DIR.is_dir()
+fn dir_entries(dir: &Path) -> Result<Vec<DirEntry>> {
+    Ok(read_dir(dir)
+        .io_context(|| format!("Failed to read directory: {:?}", dir))?
+        .map(Result::unwrap)
+        .collect())
 }
 
-fn create_cache() -> std::io::Result<()> {
-    // This function now only ensures the top-level cache directory exists.
-    // The specific git repo cache directory (GIT_REPO_CACHE_DIR)
-    // will be created by `git clone` if it doesn't exist.
-    if !CACHE_DIR.exists() {
-        std::fs::create_dir_all(CACHE_DIR.as_path())?;
+/// Runs `command` like [`Command::output`], capturing its stdout, but kills it
+/// and fails if it hasn't finished after `timeout`. Stderr is only captured if
+/// the caller asked for it to be piped. `name` describes the command in errors.
+fn output_with_timeout(command: &mut Command, name: &str, timeout: Duration) -> Result<Output> {
+    let git_error = |source| Error::Git {
+        command: name.to_string(),
+        source,
+    };
+    let mut child = command.stdout(Stdio::piped()).spawn().map_err(git_error)?;
+
+    // Read the pipes while waiting, so the child can't block on a full pipe.
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(git_error)? {
+            break status;
+        }
+        if start.elapsed() >= timeout {
+            // Reap the killed process so it doesn't linger as a zombie.
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::GitTimeout {
+                command: name.to_string(),
+                timeout,
+            });
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// The file the response for `url` is cached in, named by a hash of the URL
+/// so that no two URLs share one.
+fn http_cache_path(url: &str) -> PathBuf {
+    HTTP_CACHE_DIR.join(format!("{:016x}", stable_hash(url.as_bytes())))
+}
+
+/// Returns the cached response for `url`, unless it is missing or older than
+/// [`HTTP_CACHE_TTL`].
+fn read_cached_response(url: &str) -> Option<String> {
+    let path = http_cache_path(url);
+    let age = path.metadata().ok()?.modified().ok()?.elapsed().ok()?;
+    if age > HTTP_CACHE_TTL {
+        return None;
     }
-    Ok(())
+    read_to_string(path).ok()
+}
+
+/// Starts a GET request for `url`. Like curl, proxies are taken from
+/// `HTTPS_PROXY`, `HTTP_PROXY` and `ALL_PROXY`, skipping the hosts in
+/// `NO_PROXY`, unless `--proxy` gives one, see [`proxy_settings`].
+pub fn http_get(url: &str) -> RequestBuilder {
+    let request = attohttpc::get(url);
+    match PROXY_OVERRIDE.get() {
+        Some(proxy) => {
+            let no_proxy = std::env::var("NO_PROXY").or_else(|_| std::env::var("no_proxy"));
+            request.proxy_settings(proxy_settings(proxy, no_proxy.ok().as_deref()))
+        }
+        // attohttpc reads the environment by default.
+        None => request,
+    }
+}
+
+/// Uses `proxy` for all requests except those to the hosts in the
+/// comma-separated `no_proxy`, where `*` disables the proxy entirely.
+fn proxy_settings(proxy: &Url, no_proxy: Option<&str>) -> ProxySettings {
+    let hosts = no_proxy.unwrap_or_default();
+    if hosts.trim() == "*" {
+        return ProxySettings::builder().build();
+    }
+    hosts
+        .split(',')
+        .map(|host| host.trim().trim_start_matches('.'))
+        .filter(|host| !host.is_empty())
+        .fold(
+            ProxySettings::builder()
+                .http_proxy(proxy.clone())
+                .https_proxy(proxy.clone()),
+            |builder, host| builder.add_no_proxy_host(host),
+        )
+        .build()
+}
+
+/// A `git` command that goes through `--proxy` if it is given. Otherwise git
+/// picks up the proxy from its config or the environment on its own.
+fn git_command() -> Command {
+    let mut command = Command::new("git");
+    if let Some(proxy) = PROXY_OVERRIDE.get() {
+        command.arg("-c").arg(format!("http.proxy={proxy}"));
+    }
+    command
+}
+
+fn write_cached_response(url: &str, body: &str) -> io::Result<()> {
+    std::fs::create_dir_all(HTTP_CACHE_DIR.as_path())?;
+    std::fs::write(http_cache_path(url), body)
+}
+
+/// Why [`fetch_template_body`] failed, with the reason for reporting.
+#[derive(Debug)]
+enum FetchFailure {
+    /// The request didn't go through, or upstream failed or is rate limiting,
+    /// so the template may well exist.
+    Unavailable(String),
+    /// Upstream answered, e.g. with a 404, so the template doesn't exist there.
+    Rejected(String),
+}
+
+impl fmt::Display for FetchFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchFailure::Unavailable(reason) | FetchFailure::Rejected(reason) => {
+                f.write_str(reason)
+            }
+        }
+    }
+}
+
+impl FetchFailure {
+    /// The failure for an unsuccessful response with `status`.
+    fn from_status(status: StatusCode) -> Self {
+        let reason = format!("HTTP Status: {}", status.as_str().yellow());
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            FetchFailure::Unavailable(reason)
+        } else {
+            FetchFailure::Rejected(reason)
+        }
+    }
+}
+
+/// Fetches the body of a template, serving it from the on-disk response cache
+/// when possible. On failure the reason is returned for reporting.
+fn fetch_template_body(url: &str, no_cache: bool, verbose: bool) -> Result<String, FetchFailure> {
+    if !no_cache && let Some(body) = read_cached_response(url) {
+        if verbose {
+            eprintln!("VERBOSE: Using cached response for: {}", url.yellow());
+        }
+        return Ok(body);
+    }
+
+    let unavailable = |e: attohttpc::Error| {
+        FetchFailure::Unavailable(format!("Error: {}", e.to_string().yellow()))
+    };
+    let res = http_get(url).send().map_err(unavailable)?;
+    if !res.is_success() {
+        return Err(FetchFailure::from_status(res.status()));
+    }
+    let body = res.text().map_err(unavailable)?;
+
+    if let Err(e) = write_cached_response(url, &body)
+        && verbose
+    {
+        eprintln!("VERBOSE: Could not cache response for {}: {}", url, e);
+    }
+
+    Ok(body)
 }
 
 // Helper function to apply capitalization similar to the Zsh script's logic.
 fn capitalize_template_spec(spec: &str, debug: bool) -> String {
     let parts: Vec<String> = spec
         .split('/')
-        .map(|part| {
-            if part.chars().any(|c| c.is_ascii_uppercase()) {
+        .enumerate()
+        .map(|(i, part)| {
+            // The community templates live in a lowercase `community/` directory.
+            if i == 0 && part.eq_ignore_ascii_case("community") {
+                "community".to_string()
+            } else if part.chars().any(|c| c.is_ascii_uppercase()) {
                 part.to_string()
             } else {
                 let mut capitalized_part = String::new();
@@ -198,69 +728,694 @@ fn capitalize_template_spec(spec: &str, debug: bool) -> String {
     result
 }
 
-/// Fetches templates directly from github/gitignore and appends them to the local .gitignore file or prints to stdout.
-pub fn fetch_and_append_github_templates(
-    template_specs: &[String],
-    verbose: bool,
-    debug: bool,
-    write_to_file_flag: bool,
-    // force_write is not used by this function as it always appends if write_to_file_flag is true.
-) -> Result<()> {
-    if debug {
-        eprintln!("DEBUG: fetch_and_append_github_templates ENTERED");
+/// Checks that an existing `path` can be written to, so users get a clear error
+/// instead of a raw OS error. Symlinks are followed if `follow_symlinks` is set,
+/// otherwise writing to them is refused.
+pub fn ensure_writable(path: &Path, follow_symlinks: bool, verbose: bool) -> Result<()> {
+    let Ok(metadata) = path.symlink_metadata() else {
+        // Doesn't exist yet, so it will be created.
+        return Ok(());
+    };
+
+    if metadata.file_type().is_symlink() {
+        let target =
+            std::fs::read_link(path).io_context(|| format!("Failed to read {}", path.display()))?;
+        if !follow_symlinks {
+            return Err(Error::NotWritable {
+                path: path.to_path_buf(),
+                reason: format!(
+                    " is a symlink to {}, refusing to write to it because of --no-follow-symlinks",
+                    target.display()
+                ),
+            });
+        }
+        if verbose {
+            eprintln!(
+                "VERBOSE: {} is a symlink to {}, following it.",
+                path.display(),
+                target.display()
+            );
+        }
     }
 
-    if template_specs.is_empty() {
-        // This should ideally be caught by clap if templates are required.
-        eprintln!("{}", "Error: No gitignore template specified.".red());
-        // Consider printing usage instructions or returning an error that main can handle.
+    let metadata = path
+        .metadata()
+        .io_context(|| format!("{} is a broken symlink", path.display()))?;
+    if metadata.permissions().readonly() {
+        return Err(Error::NotWritable {
+            path: path.to_path_buf(),
+            reason: " is read-only, check its permissions before writing to it".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// The root of the git working tree containing `dir`, by walking up to the
+/// closest `.git` directory or `.git` file of a worktree.
+pub fn git_work_tree(dir: &Path) -> Option<&Path> {
+    dir.ancestors().find(|dir| dir.join(".git").exists())
+}
+
+/// Checks that `dir` is inside a git working tree before a `.gitignore` is
+/// written to it. Outside of one this is an error if `require` is set, and a
+/// note otherwise.
+pub fn ensure_in_git_repo(dir: &Path, require: bool, quiet: bool) -> Result<()> {
+    if git_work_tree(dir).is_some() {
         return Ok(());
     }
+    if require {
+        return Err(Error::NotWritable {
+            path: dir.join(GITIGNORE_FILE_NAME),
+            reason: " is not inside a git repository, refusing to write to it because of --require-git-repo"
+                .to_string(),
+        });
+    }
+    if !quiet {
+        eprintln!(
+            "{}: {} is not inside a git repository, writing {} anyway.",
+            "Info".bold().green(),
+            dir.display(),
+            GITIGNORE_FILE_NAME
+        );
+    }
+    Ok(())
+}
 
-    let gitignore_path = Path::new(GITIGNORE_FILE_NAME);
-    let mut existing_lines = HashSet::new();
-    // Collects all unique new lines from all templates for this session, to be written/printed once.
-    let mut session_lines_to_add = Vec::new();
+/// Takes an advisory lock for writing to the `.gitignore` at `path`, waiting
+/// for other processes writing to it, so their read-modify-writes can't
+/// interleave. The lock is released when the returned file is dropped.
+///
+/// The lock files are kept in the user's cache directory instead of next to the
+/// `.gitignore`, so they don't show up in the repository. They are never
+/// removed, removing them would let a waiting process lock a stale file.
+pub fn lock_gitignore(path: &Path) -> Result<File> {
+    let lock_path = write_lock_path(path)?;
+    std::fs::create_dir_all(WRITE_LOCK_DIR.as_path())
+        .io_context(|| format!("Failed to create {}", WRITE_LOCK_DIR.display()))?;
+    let lock_file = File::create(&lock_path)
+        .io_context(|| format!("Failed to create lock file at {:?}", lock_path))?;
+    match lock_file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            eprintln!(
+                "{}: Waiting for another process to finish writing to {}...",
+                "Info".bold().green(),
+                path.display()
+            );
+            lock_file
+                .lock()
+                .io_context(|| format!("Failed to lock {:?}", lock_path))?;
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(Error::Io {
+                context: format!("Failed to lock {:?}", lock_path),
+                source: e,
+            });
+        }
+    }
+    Ok(lock_file)
+}
 
-    if write_to_file_flag {
-        if !gitignore_path.exists() {
-            File::create(gitignore_path)?;
-            if verbose {
-                eprintln!("VERBOSE: Created {}.", GITIGNORE_FILE_NAME.cyan());
+/// The lock file for writing to `path`, the same for every way of spelling it.
+fn write_lock_path(path: &Path) -> Result<PathBuf> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let dir = dir
+        .unwrap_or(Path::new("."))
+        .canonicalize()
+        .io_context(|| format!("Failed to resolve the directory of {}", path.display()))?;
+    let file_name = path.file_name().unwrap_or(GITIGNORE_FILE_NAME.as_ref());
+    let hash = stable_hash(dir.join(file_name).as_os_str().as_encoded_bytes());
+    Ok(WRITE_LOCK_DIR.join(format!("{hash:016x}.lock")))
+}
+
+/// Hashes `bytes` the same way in every build, unlike [`std::hash::DefaultHasher`],
+/// for names of files that other runs have to find. This is 64-bit FNV-1a.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The byte order mark some Windows editors expect at the start of a file.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// The bytes of a newly created `.gitignore` with `content`, see [`OutputEncoding`].
+pub fn encode_new(content: &str, encoding: OutputEncoding) -> Vec<u8> {
+    match encoding {
+        OutputEncoding::Utf8 => content.as_bytes().to_vec(),
+        OutputEncoding::Utf8Bom => [UTF8_BOM, content.as_bytes()].concat(),
+    }
+}
+
+/// The encoding to rewrite the existing file at `path` with, so a byte order
+/// mark it starts with is kept although [`read_gitignore`] drops it.
+pub fn existing_encoding(path: &Path) -> OutputEncoding {
+    let mut start = [0; UTF8_BOM.len()];
+    let has_bom = File::open(path)
+        .and_then(|mut file| file.read_exact(&mut start))
+        .is_ok_and(|()| start == UTF8_BOM);
+    if has_bom {
+        OutputEncoding::Utf8Bom
+    } else {
+        OutputEncoding::Utf8
+    }
+}
+
+/// Reads a `.gitignore`, dropping a leading byte order mark some Windows
+/// editors add, so the first pattern compares equal to the same pattern in a
+/// template. Invalid UTF-8 is replaced instead of failing, with a warning.
+pub fn read_gitignore(path: &Path) -> io::Result<String> {
+    let bytes = std::fs::read(path)?;
+    let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(&bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(content) => Ok(content.to_string()),
+        Err(_) => {
+            eprintln!(
+                "{}: {} is not valid UTF-8, invalid bytes are replaced.",
+                "Warning".yellow(),
+                path.display()
+            );
+            Ok(String::from_utf8_lossy(bytes).into_owned())
+        }
+    }
+}
+
+/// Drops the lines of `content` that one of the files at `paths` already has,
+/// like [`merge::new_lines`] does, see `--against`.
+pub fn without_lines_in(content: &str, paths: &[PathBuf]) -> Result<String> {
+    if paths.is_empty() {
+        return Ok(content.to_string());
+    }
+    let mut known = String::new();
+    for path in paths {
+        let content =
+            read_gitignore(path).io_context(|| format!("Failed to read {}", path.display()))?;
+        known.push_str(&content);
+        known.push('\n');
+    }
+    Ok(merge::new_lines(&known, content).unwrap_or_default())
+}
+
+/// How [`write_gitignore`] writes a `.gitignore`.
+#[derive(Debug, Clone, Copy)]
+pub struct WriteOptions<'a> {
+    pub verbose: bool,
+    /// Whether to hold the lock while writing, see [`lock_gitignore`].
+    pub lock: bool,
+    pub follow_symlinks: bool,
+    /// Whether the existing content is overwritten instead of updated.
+    pub force: bool,
+    /// The encoding of a created or overwritten file, an existing one keeps its own.
+    pub encoding: OutputEncoding,
+    /// The footer kept at the end of the file, see [`merge::with_footer`].
+    pub footer: Option<&'a str>,
+    /// The cache commit rendered into the footer.
+    pub commit: Option<&'a str>,
+}
+
+/// What [`write_gitignore`] wrote.
+#[derive(Debug)]
+pub struct Written {
+    /// The content before, `None` if the file was created or overwritten.
+    pub old: Option<String>,
+    /// The content written, with the footer.
+    pub new: String,
+}
+
+/// Reads the `.gitignore` at `path`, passes its content to `update` and writes
+/// what that returns, `None` leaves the file as it is. Every `.gitignore` is
+/// written through here, so the lock, the encoding and the footer are handled
+/// the same for all of them.
+///
+/// The lock is held from reading until writing, so no other write can come in
+/// between. `update` gets the content without the footer, which is put back at
+/// the end afterwards, and `None` if the file doesn't exist yet or is
+/// overwritten with `force`.
+pub fn write_gitignore<E: From<Error>>(
+    path: &Path,
+    options: &WriteOptions<'_>,
+    update: impl FnOnce(Option<&str>) -> Result<Option<String>, E>,
+) -> Result<Option<Written>, E> {
+    ensure_writable(path, options.follow_symlinks, options.verbose)?;
+    let _lock = options.lock.then(|| lock_gitignore(path)).transpose()?;
+    let old = match read_gitignore(path) {
+        Ok(_) if options.force => None,
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(Error::Io {
+                context: format!("Failed to read {}", path.display()),
+                source: e,
             }
+            .into());
         }
+    };
+    let current = old.as_deref().map(|content| {
+        options
+            .footer
+            .map_or(content, |footer| data::without_footer(content, footer))
+    });
+    let Some(new) = update(current)? else {
+        return Ok(None);
+    };
+    let new = match options.footer {
+        Some(footer) => merge::with_footer(&new, footer, options.commit),
+        None => new,
+    };
+    // A byte order mark only belongs at the very start of the file.
+    let encoding = match old {
+        Some(_) => existing_encoding(path),
+        None => options.encoding,
+    };
+    std::fs::write(path, encode_new(&new, encoding))
+        .io_context(|| format!("Failed to write to {}", path.display()))?;
+    Ok(Some(Written { old, new }))
+}
 
-        match read_to_string(gitignore_path) {
-            Ok(content) => {
-                for line in content.lines() {
-                    existing_lines.insert(line.trim_end().to_string());
-                }
-                if debug {
-                    eprintln!(
-                        "DEBUG: Loaded {} lines from existing .gitignore.",
-                        existing_lines.len()
-                    );
-                }
+/// Finds the `info/exclude` file of the git repository containing `dir`, see
+/// [`git_work_tree`].
+pub fn git_exclude_path(dir: &Path) -> Option<PathBuf> {
+    let git = git_work_tree(dir)?.join(".git");
+    let git_dir = if git.is_file() {
+        let content = read_to_string(&git).ok()?;
+        let git_dir = content.trim().strip_prefix("gitdir:")?.trim();
+        git.parent()?.join(git_dir)
+    } else {
+        git
+    };
+    Some(git_dir.join("info").join("exclude")).filter(|path| path.is_file())
+}
+
+/// Collects the lines of a template that aren't in `existing_lines` yet, marking
+/// them as existing for subsequent templates. Returns the new lines and the
+/// number of lines that already existed.
+///
+/// Negations (`!pattern`) only have an effect after the patterns they negate,
+/// so once a pattern from the template is added, later negations are kept even
+/// if they already exist to preserve their position relative to it.
+///
+/// With `smart_dedup`, a line also counts as existing if its
+/// [`canonical_pattern`] does, `existing_lines` has to contain the canonical
+/// patterns as well then.
+///
+/// Blank lines are skipped, unless `preserve_blanks` is set. Then runs of them
+/// between the collected lines are kept as a single blank line.
+fn collect_new_lines(
+    body: &str,
+    existing_lines: &mut HashSet<String>,
+    smart_dedup: bool,
+    preserve_blanks: bool,
+    verbose: bool,
+) -> (Vec<String>, usize) {
+    let mut new_lines: Vec<String> = Vec::new();
+    let mut existed_lines = 0;
+    let mut added_pattern = false;
+
+    for line_raw in body.lines() {
+        let line = trim_pattern(line_raw);
+
+        if line.is_empty() {
+            if preserve_blanks && new_lines.last().is_some_and(|last| !last.is_empty()) {
+                new_lines.push(String::new());
+            } else if verbose {
+                eprintln!("VERBOSE: Skipping empty line from template.");
             }
-            Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                if debug {
-                    eprintln!("DEBUG: .gitignore not found, will be created if lines are added.");
-                }
+            continue;
+        }
+
+        if verbose {
+            eprintln!("VERBOSE: Checking line: '{}'", line);
+        }
+
+        let is_negation = line.starts_with('!');
+        let canonical = smart_dedup.then(|| canonical_pattern(line));
+        let exists = existing_lines.contains(line)
+            || canonical
+                .as_ref()
+                .is_some_and(|canonical| existing_lines.contains(canonical));
+        if exists && !(is_negation && added_pattern) {
+            if verbose {
+                eprintln!("VERBOSE: Line already exists: '{}'", line.italic());
             }
-            Err(e) => {
-                return Err(anyhow::Error::new(e)
-                    .context(format!("Failed to read {}", GITIGNORE_FILE_NAME)));
+            existed_lines += 1;
+        } else {
+            if verbose {
+                eprintln!(
+                    "VERBOSE: New line, collecting for session: '{}'",
+                    line.green()
+                );
             }
+            if !is_negation && !line.starts_with('#') {
+                added_pattern = true;
+            }
+            new_lines.push(line.to_string());
+            existing_lines.insert(line.to_string()); // Mark as existing for subsequent templates in this run
+            existing_lines.extend(canonical);
         }
     }
 
-    let mut overall_new_lines_count_for_session = 0;
-    let mut succeeded_templates_list = String::new();
-    let mut failed_templates_list = String::new();
+    if new_lines.last().is_some_and(String::is_empty) {
+        new_lines.pop();
+    }
+    (new_lines, existed_lines)
+}
 
-    for template_spec_original in template_specs {
-        let template_spec_for_url = capitalize_template_spec(template_spec_original, debug);
+/// Removes the lines of `content` that already appeared earlier in it, and
+/// collapses runs of blank lines into one. Returns the tidied content and how
+/// many lines were removed as duplicates.
+///
+/// A repeated pattern still has an effect if a negation came in between, as it
+/// ignores the files the negation brought back, and the other way around, so
+/// such repeats are kept. With `smart_dedup`, lines are compared by their
+/// [`canonical_pattern`].
+pub fn tidy(content: &str, smart_dedup: bool) -> (String, usize) {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut duplicates = 0;
+    let mut comments = HashSet::new();
+    let mut patterns = HashSet::new();
+    let mut negations = HashSet::new();
+    for line in content.lines().map(trim_pattern) {
+        if line.is_empty() {
+            if lines.last().is_some_and(|last| !last.is_empty()) {
+                lines.push(line);
+            }
+            continue;
+        }
+        let key = if smart_dedup {
+            canonical_pattern(line)
+        } else {
+            line.to_string()
+        };
+        let (seen, opposite) = if line.starts_with('#') {
+            (&mut comments, None)
+        } else if line.starts_with('!') {
+            (&mut negations, Some(&mut patterns))
+        } else {
+            (&mut patterns, Some(&mut negations))
+        };
+        let is_new = seen.insert(key);
+        if is_new && let Some(opposite) = opposite {
+            opposite.clear();
+        }
+        if is_new {
+            lines.push(line);
+        } else {
+            duplicates += 1;
+        }
+    }
+    if lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
 
+    let mut tidied = lines.join("\n");
+    if !tidied.is_empty() {
+        tidied.push('\n');
+    }
+    (tidied, duplicates)
+}
+
+/// Normalizes the spellings of a pattern that gitignore treats the same, so
+/// `--smart-dedup` can compare them:
+///
+/// - A leading `/` is dropped if the pattern has another `/` before its end,
+///   such patterns are relative to the `.gitignore` anyway: `/foo/bar` is
+///   `foo/bar`.
+/// - A leading `**/` is dropped if the rest has no `/` before its end, such
+///   patterns match at any depth anyway: `**/foo/` is `foo/`.
+///
+/// This is deliberately conservative. `foo`, `foo/` and `/foo/` match different
+/// paths and are kept apart, and comments and patterns with escapes are
+/// returned as is.
+fn canonical_pattern(line: &str) -> String {
+    if line.starts_with('#') || line.contains('\\') {
+        return line.to_string();
+    }
+    let (negation, pattern) = match line.strip_prefix('!') {
+        Some(pattern) => ("!", pattern),
+        None => ("", line),
+    };
+    let has_inner_slash = |pattern: &str| pattern.trim_end_matches('/').contains('/');
+    let pattern = match pattern.strip_prefix("**/") {
+        Some(rest) if !rest.is_empty() && !has_inner_slash(rest) => rest,
+        _ => match pattern.strip_prefix('/') {
+            Some(rest) if has_inner_slash(rest) => rest,
+            _ => pattern,
+        },
+    };
+    format!("{negation}{pattern}")
+}
+
+/// Anchors the patterns of `content` under the directory `dir`, so they only
+/// match inside it, see `--scope`. Patterns that match at any depth, like
+/// `target/`, match at any depth below `dir`, while anchored ones, like
+/// `/dist` or `src/gen`, are anchored to `dir` instead. Negations stay
+/// negations, comments and blank lines are kept as they are.
+pub fn scope_patterns(content: &str, dir: &str) -> String {
+    let dir = dir.trim_matches('/');
+    if dir.is_empty() {
+        return content.to_string();
+    }
+    let has_inner_slash = |pattern: &str| pattern.trim_end_matches('/').contains('/');
+    let mut scoped = String::new();
+    for line in content.lines() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            scoped.push_str(line);
+        } else {
+            let (negation, pattern) = match line.strip_prefix('!') {
+                Some(pattern) => ("!", pattern),
+                None => ("", line),
+            };
+            let pattern = match pattern.strip_prefix('/') {
+                Some(rest) => format!("/{dir}/{rest}"),
+                None if pattern.starts_with("**/") || has_inner_slash(pattern) => {
+                    format!("/{dir}/{pattern}")
+                }
+                None => format!("/{dir}/**/{pattern}"),
+            };
+            scoped.push_str(negation);
+            scoped.push_str(&pattern);
+        }
+        scoped.push('\n');
+    }
+    scoped
+}
+
+/// Reports the templates that didn't produce any content, the same way for
+/// directly fetched templates and templates from the cache.
+pub fn report_failed_templates(names: &[String]) {
+    if !names.is_empty() {
+        eprintln!(
+            "{}: {}",
+            "Failed to fetch or process template(s)".red(),
+            names.join(", ").yellow()
+        );
+    }
+}
+
+/// Prints the lines added to `.gitignore` for each template, for `--show-added`.
+/// Blank lines and templates that added nothing are left out.
+pub fn report_added_lines(added: &[(String, Vec<String>)]) {
+    let mut reported_any = false;
+    for (template, lines) in added {
+        let mut lines = lines
+            .iter()
+            .filter(|line| !line.trim().is_empty())
+            .peekable();
+        if lines.peek().is_none() {
+            continue;
+        }
+        eprintln!("Added from {}:", template.cyan());
+        for line in lines {
+            eprintln!("  {} {}", "+".green(), line);
+        }
+        reported_any = true;
+    }
+    if !reported_any {
+        eprintln!("No lines were added.");
+    }
+}
+
+/// Options for [`fetch_and_append_github_templates`].
+#[derive(Debug, Clone, Copy)]
+pub struct FetchOptions<'a> {
+    pub verbose: bool,
+    pub debug: bool,
+    /// Append to `.gitignore` instead of printing to stdout.
+    pub write: bool,
+    /// The files to write to and diff against, `.gitignore` if empty.
+    pub paths: &'a [PathBuf],
+    /// With `write`, replace the content of `.gitignore` instead of appending to it.
+    pub force: bool,
+    /// Insert the new lines at the top of `.gitignore` instead of appending them.
+    pub prepend: bool,
+    /// How the new lines are merged into `.gitignore`, see [`merge::merge`].
+    pub merge_strategy: MergeStrategy,
+    /// With [`TemplateCase::Exact`], names are used in URLs as given instead of
+    /// being capitalized.
+    pub template_case: TemplateCase,
+    /// Try templates inside this subdirectory first, see [`data::with_namespace`].
+    pub namespace: Option<&'a str>,
+    /// How the templates are printed when they aren't written to `.gitignore`.
+    pub output_format: OutputFormat,
+    /// Also skip lines that are equivalent to existing ones, see [`canonical_pattern`].
+    pub smart_dedup: bool,
+    /// Keep single blank lines from the templates when printing them.
+    pub preserve_blanks: bool,
+    /// Print only the patterns, see [`bare`].
+    pub bare: bool,
+    /// Anchor the patterns under this directory, see [`scope_patterns`].
+    pub scope: Option<&'a str>,
+    /// With `write`, print the lines added for each template, see [`report_added_lines`].
+    pub show_added: bool,
+    /// Fail with [`Error::NothingAdded`] if the templates add no new lines.
+    pub fail_on_empty: bool,
+    /// Read the existing content from stdin and echo it back to stdout.
+    pub filter: bool,
+    /// Fail before writing anything if any template can't be fetched.
+    pub strict: bool,
+    /// Bypass the on-disk response cache.
+    pub no_cache: bool,
+    pub source: Source,
+    /// Lock `.gitignore` while reading and writing it, see [`lock_gitignore`].
+    pub lock: bool,
+    /// The encoding of `.gitignore` when it is created or overwritten.
+    pub output_encoding: OutputEncoding,
+    /// Write through a symlinked `.gitignore` instead of refusing to.
+    pub follow_symlinks: bool,
+    /// Added in front of the new lines, see [`data::render_banner`].
+    pub banner: Option<&'a str>,
+    /// Added at the end of `.gitignore` when it changes, see [`merge::with_footer`].
+    pub footer: Option<&'a str>,
+    /// Don't add the banner if the existing content already has one.
+    pub append_header_once: bool,
+    /// Print a diff of the changes to `.gitignore` instead of writing them.
+    pub diff: bool,
+    /// Reference files whose lines are skipped, in addition to the existing content.
+    pub against: &'a [PathBuf],
+}
+
+/// Only the patterns of `content`, leaving out comments and blank lines, see
+/// `--bare`. Leading whitespace is part of a pattern, so it's kept.
+pub fn bare(content: &str) -> String {
+    content
+        .lines()
+        .filter(|line| {
+            let trimmed = line.trim();
+            !trimmed.is_empty() && !trimmed.starts_with('#')
+        })
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+/// Formats the templates for stdout, see [`OutputFormat`].
+pub fn format_output(content: &str, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Raw => content.to_string(),
+        OutputFormat::Markdown => format!("```gitignore\n{}\n```\n", content.trim_matches('\n')),
+    }
+}
+
+/// Builds the `(original spec, spec used in the URL, URL)` requests for the
+/// given templates. github/gitignore serves one file per template, while
+/// gitignore.io combines all of them in a single response. Unless
+/// `template_case` is [`TemplateCase::Exact`], the names are capitalized like
+/// the files in github/gitignore.
+fn template_requests(
+    template_specs: &[String],
+    source: Source,
+    template_case: TemplateCase,
+    debug: bool,
+) -> Vec<(String, String, String)> {
+    match source {
+        Source::Github => template_specs
+            .iter()
+            .map(|spec| {
+                let spec_for_url = match data::synonym(spec) {
+                    Some(key) => key.to_string(),
+                    None if template_case == TemplateCase::Exact => spec.clone(),
+                    None => capitalize_template_spec(spec, debug),
+                };
+                let url = format!(
+                    "{}{}.gitignore",
+                    GITHUB_GITIGNORE_BASE_URL,
+                    spec_for_url.replace(' ', "%20")
+                );
+                (spec.clone(), spec_for_url, url)
+            })
+            .collect(),
+        Source::GitignoreIo => {
+            let spec = template_specs.join(",");
+            let spec_for_url = spec.to_lowercase();
+            let url = format!("{}{}", GITIGNORE_IO_API_URL, spec_for_url);
+            vec![(spec, spec_for_url, url)]
+        }
+    }
+}
+
+/// The URL the template with the key `spec` is fetched from.
+pub fn template_url(spec: &str, source: Source) -> String {
+    let (_, _, url) =
+        template_requests(&[spec.to_string()], source, TemplateCase::Exact, false).remove(0);
+    url
+}
+
+/// Prints the URL each template would be fetched from, without fetching it.
+pub fn print_template_urls(
+    template_specs: &[String],
+    source: Source,
+    template_case: TemplateCase,
+    debug: bool,
+) {
+    for (spec, _, url) in template_requests(template_specs, source, template_case, debug) {
+        println!("{}: {}", spec.cyan(), url);
+    }
+}
+
+/// A template fetched by [`fetch_templates`].
+struct FetchedTemplate {
+    /// The name the template was requested by.
+    name: String,
+    /// The name the template was fetched as.
+    fetched_as: String,
+    body: String,
+}
+
+/// Fetches the templates for `template_specs` directly, falling back to the
+/// local cache if that fails. Also returns the names that couldn't be fetched,
+/// they have been reported already.
+fn fetch_templates(
+    template_specs: &[String],
+    options: &FetchOptions<'_>,
+) -> (Vec<FetchedTemplate>, Vec<String>) {
+    let FetchOptions {
+        verbose,
+        debug,
+        template_case,
+        namespace,
+        scope,
+        no_cache,
+        source,
+        ..
+    } = *options;
+
+    let mut fetched_templates = Vec::new();
+    let mut failed = Vec::new();
+    let requests = template_requests(template_specs, source, template_case, debug);
+    // Only read when a fetch fails, to serve the template from the local cache
+    // instead, or to look templates up in a namespace.
+    let cached_data = std::cell::OnceCell::new();
+    let cached_data = || {
+        cached_data
+            .get_or_init(|| {
+                UserData::new()
+                    .and_then(|user_data| data::IgnoreData::new(&user_data))
+                    .ok()
+            })
+            .as_ref()
+    };
+
+    for (template_spec_original, template_spec_for_url, fetch_url) in &requests {
         if verbose {
             eprintln!(
                 "\nVERBOSE: Processing template: '{}' (attempting as '{}')...",
@@ -269,185 +1424,850 @@ pub fn fetch_and_append_github_templates(
             );
         }
 
-        let template_file_path_in_repo = format!("{}.gitignore", template_spec_for_url);
-        let fetch_url = format!(
-            "{}{}",
-            GITHUB_GITIGNORE_BASE_URL, template_file_path_in_repo
-        );
-
-        if verbose {
-            eprintln!("VERBOSE: Fetching from: {}", fetch_url.yellow());
+        let mut fetched = None;
+        let namespaced = namespace
+            .filter(|_| source == Source::Github)
+            .and_then(|namespace| data::with_namespace(template_spec_original, namespace))
+            .and_then(
+                |spec| match cached_data().filter(|_| data::GIT_REPO_CACHE_DIR.is_dir()) {
+                    // Saves a request that mostly fails because the template isn't namespaced.
+                    Some(data) => data.template_key(&spec).map(String::from),
+                    None => Some(spec),
+                },
+            );
+        if let Some(spec) = namespaced {
+            let (_, spec_for_url, url) =
+                template_requests(&[spec], source, template_case, debug).remove(0);
+            if verbose {
+                eprintln!("VERBOSE: Fetching from: {}", url.yellow());
+            }
+            match fetch_template_body(&url, no_cache, verbose) {
+                Ok(body) => fetched = Some((spec_for_url, body)),
+                Err(reason) if verbose => eprintln!(
+                    "VERBOSE: Not found in the namespace ({reason}), falling back to '{}'",
+                    template_spec_original.cyan()
+                ),
+                Err(_) => {}
+            }
         }
 
-        let response = attohttpc::get(&fetch_url).send();
-        // let mut current_template_had_content = false; // This variable was unused
-
-        match response {
-            Ok(res) => {
-                if res.is_success() {
-                    let body = res.text()?;
-                    // current_template_had_content = !body.is_empty(); // Assignment removed
-                    succeeded_templates_list.push_str(&format!("{} ", template_spec_original));
-
-                    if body.is_empty() && verbose {
+        let fetched = fetched.map(Ok).unwrap_or_else(|| {
+            if verbose {
+                eprintln!("VERBOSE: Fetching from: {}", fetch_url.yellow());
+            }
+            fetch_template_body(fetch_url, no_cache, verbose)
+                .map(|body| (template_spec_for_url.clone(), body))
+        });
+        let fetched = fetched.or_else(|failure| {
+            // A template that doesn't exist upstream anymore isn't served from a stale cache.
+            if !matches!(failure, FetchFailure::Unavailable(_)) {
+                return Err(failure);
+            }
+            let cached = (source == Source::Github)
+                .then(cached_data)
+                .flatten()
+                .and_then(|data| data.get_template(template_spec_for_url).ok()?);
+            match cached {
+                Some(body) => {
+                    if verbose {
                         eprintln!(
-                            "VERBOSE: Note: Template '{}' (fetched as '{}') is empty.",
-                            template_spec_original.cyan(),
+                            "VERBOSE: Failed to fetch '{}' ({failure}), using the local cache instead",
                             template_spec_for_url.cyan()
                         );
                     }
-
-                    let mut current_template_new_lines_added_to_session = 0;
-                    let mut current_template_existed_lines = 0;
-
-                    for line_raw in body.lines() {
-                        let line = line_raw.trim_end();
-
-                        if line.is_empty() {
-                            if verbose {
-                                eprintln!("VERBOSE: Skipping empty line from template.");
-                            }
-                            continue;
-                        }
-
-                        if verbose {
-                            eprintln!("VERBOSE: Checking line: '{}'", line);
-                        }
-
-                        if existing_lines.contains(line) {
-                            if verbose {
-                                eprintln!("VERBOSE: Line already exists: '{}'", line.italic());
-                            }
-                            current_template_existed_lines += 1;
-                        } else {
-                            if verbose {
-                                eprintln!(
-                                    "VERBOSE: New line, collecting for session: '{}'",
-                                    line.green()
-                                );
-                            }
-                            session_lines_to_add.push(line.to_string());
-                            existing_lines.insert(line.to_string()); // Mark as existing for subsequent templates in this run
-                            current_template_new_lines_added_to_session += 1;
-                        }
-                    }
-                    overall_new_lines_count_for_session +=
-                        current_template_new_lines_added_to_session;
-
-                    if write_to_file_flag && current_template_new_lines_added_to_session > 0 {
-                        // Message per template if writing to file and new lines were found for *this* template
-                        println!(
-                            "Collected {} new line(s) from '{}' for current session.",
-                            current_template_new_lines_added_to_session,
-                            template_spec_original.cyan()
-                        );
-                    }
-
-                    if current_template_new_lines_added_to_session == 0
-                        && current_template_existed_lines > 0
-                        // && current_template_had_content // Condition removed as variable is removed
-                    {
-                        // If the template had content (checked by body.is_empty() earlier)
-                        // and no new lines were added, but some existed, this message is appropriate.
-                        // The check for `body.is_empty()` at the beginning of the success block
-                        // already handles the case for truly empty templates.
-                        if verbose || write_to_file_flag {
-                            // Show this if writing or verbose
-                            println!(
-                                "All patterns from '{}' (fetched as '{}') already existed or were duplicates (template was not empty).",
-                                template_spec_original.cyan(),
-                                template_spec_for_url.cyan()
-                            );
-                        }
-                    }
-                } else {
-                    eprintln!(
-                        "{}: Failed to fetch template '{}' (tried as '{}') - HTTP Status: {}",
-                        "Error".red().bold(),
-                        template_spec_original.cyan(),
-                        template_spec_for_url.cyan(),
-                        res.status().as_str().yellow()
-                    );
-                    failed_templates_list.push_str(&format!("{} ", template_spec_original));
+                    Ok((template_spec_for_url.clone(), body))
                 }
+                None => Err(failure),
             }
-            Err(e) => {
+        });
+        let (template_spec_for_url, body) = match fetched {
+            Ok(fetched) => fetched,
+            Err(reason) => {
                 eprintln!(
-                    "{}: Failed to fetch template '{}' (tried as '{}') - Error: {}",
+                    "{}: Failed to fetch template '{}' (tried as '{}') - {}",
                     "Error".red().bold(),
                     template_spec_original.cyan(),
                     template_spec_for_url.cyan(),
-                    e.to_string().yellow()
+                    reason
                 );
-                failed_templates_list.push_str(&format!("{} ", template_spec_original));
+                failed.push(template_spec_original.clone());
+                continue;
             }
+        };
+
+        if body.trim().is_empty() {
+            eprintln!(
+                "{}: Template '{}' (fetched as '{}') exists but is empty.",
+                "Warning".yellow(),
+                template_spec_original.cyan(),
+                template_spec_for_url.cyan()
+            );
         }
+
+        let body = match scope {
+            Some(dir) => scope_patterns(&body, dir),
+            None => body,
+        };
+        fetched_templates.push(FetchedTemplate {
+            name: template_spec_original.clone(),
+            fetched_as: template_spec_for_url,
+            body,
+        });
     }
 
-    if write_to_file_flag {
-        if !session_lines_to_add.is_empty() {
-            // Check if there are any lines collected from *any* template
-            let mut file = OpenOptions::new().append(true).open(gitignore_path)?;
+    (fetched_templates, failed)
+}
+
+/// Fetches templates directly from github/gitignore and appends them to the local .gitignore file or prints to stdout.
+/// The templates are fetched once, and merged into each of the `paths` on its own.
+pub fn fetch_and_append_github_templates(
+    template_specs: &[String],
+    options: &FetchOptions<'_>,
+) -> Result<()> {
+    let FetchOptions {
+        verbose,
+        debug,
+        paths,
+        output_format,
+        bare: bare_output,
+        fail_on_empty,
+        filter: filter_stdin,
+        strict,
+        diff: show_diff,
+        ..
+    } = *options;
+
+    if debug {
+        eprintln!("DEBUG: fetch_and_append_github_templates ENTERED");
+    }
+
+    if template_specs.is_empty() {
+        // This should ideally be caught by clap if templates are required.
+        eprintln!("{}", "Error: No gitignore template specified.".red());
+        // Consider printing usage instructions or returning an error that main can handle.
+        return Ok(());
+    }
+
+    let (fetched, failed) = fetch_templates(template_specs, options);
+    if strict && !failed.is_empty() {
+        return Err(Error::UnresolvedTemplates(failed));
+    }
+
+    let mut added_nothing = false;
+    if paths.is_empty() {
+        added_nothing = !append_fetched_templates(&fetched, None, options)?;
+    }
+    for path in paths {
+        added_nothing |= !append_fetched_templates(&fetched, Some(path), options)?;
+    }
+
+    if verbose {
+        eprint!("\nVERBOSE: ");
+    }
+    if !fetched.is_empty() {
+        let names: Vec<&str> = fetched
+            .iter()
+            .map(|template| template.name.as_str())
+            .collect();
+        let message = format!(
+            "Successfully processed template(s): {}",
+            names.join(" ").green()
+        );
+        // Keep stdout limited to the filtered content, the diff or the snippet.
+        if filter_stdin || show_diff || bare_output || output_format == OutputFormat::Markdown {
+            eprintln!("{message}");
+        } else {
+            println!("{message}");
+        }
+    }
+    report_failed_templates(&failed);
+
+    if fail_on_empty && added_nothing {
+        return Err(Error::NothingAdded);
+    }
+    if debug {
+        eprintln!("DEBUG: fetch_and_append_github_templates normal exit");
+    }
+    Ok(())
+}
+
+/// The content the sections of `content` generated with `banner` came from, see
+/// [`merge::without_sections`]. The templates named in their banners that
+/// aren't among the `fetched` ones are fetched as well, and what the templates
+/// had before comes from the history of the cache, see [`Core::template_history`].
+fn fetched_section_templates(
+    fetched: &[FetchedTemplate],
+    content: &str,
+    banner: Option<&str>,
+    options: &FetchOptions<'_>,
+) -> String {
+    let Some(banner) = banner else {
+        return String::new();
+    };
+    let names = data::section_names(content, banner);
+    let others: Vec<String> = names
+        .iter()
+        .filter(|name| {
+            !fetched
+                .iter()
+                .any(|template| template.name.eq_ignore_ascii_case(name))
+        })
+        .cloned()
+        .collect();
+    let (others, _) = if others.is_empty() {
+        (Vec::new(), Vec::new())
+    } else {
+        fetch_templates(&others, options)
+    };
+    let history = Core::default().template_history(&names);
+    let history = match options.scope {
+        Some(dir) => scope_patterns(&history, dir),
+        None => history,
+    };
+    fetched
+        .iter()
+        .chain(&others)
+        .map(|template| format!("{}\n", template.body))
+        .chain([history])
+        .collect()
+}
+
+/// Merges the `fetched` templates into `path`, `.gitignore` if `None`, or
+/// prints them, returning whether they added any lines.
+fn append_fetched_templates(
+    fetched: &[FetchedTemplate],
+    path: Option<&Path>,
+    options: &FetchOptions<'_>,
+) -> Result<bool> {
+    let FetchOptions {
+        verbose,
+        debug,
+        write: write_to_file_flag,
+        force,
+        prepend,
+        merge_strategy,
+        output_format,
+        smart_dedup,
+        preserve_blanks,
+        bare: bare_output,
+        show_added,
+        filter: filter_stdin,
+        lock,
+        output_encoding,
+        follow_symlinks,
+        banner,
+        footer,
+        append_header_once,
+        diff: show_diff,
+        against,
+        ..
+    } = *options;
+
+    let gitignore_path = path.unwrap_or(Path::new(GITIGNORE_FILE_NAME));
+    let target = gitignore_path.display().to_string();
+    // The lines the new ones are checked against, besides the current content.
+    let mut known_lines = HashSet::new();
+    // Collects all unique new lines from all templates for this session, to be written/printed once.
+    let mut session_lines_to_add = Vec::new();
+    let mut overall_new_lines_count_for_session = 0;
+    // The lines each template adds, for `show_added`.
+    let mut added_by_template = Vec::new();
+
+    // In filter mode the existing content comes from stdin instead of the file,
+    // and is echoed back in front of the new lines.
+    let mut stdin_content = String::new();
+    if filter_stdin {
+        io::stdin()
+            .read_to_string(&mut stdin_content)
+            .io_context(|| "Failed to read existing content from stdin".to_string())?;
+        for line in stdin_content.lines() {
+            known_lines.insert(trim_pattern(line).to_string());
+        }
+        if debug {
+            eprintln!("DEBUG: Loaded {} lines from stdin.", known_lines.len());
+        }
+    }
+
+    for path in against {
+        let content =
+            read_gitignore(path).io_context(|| format!("Failed to read {}", path.display()))?;
+        for line in content.lines() {
+            known_lines.insert(trim_pattern(line).to_string());
+        }
+        if debug {
+            eprintln!("DEBUG: Loaded lines from {}.", path.display());
+        }
+    }
+
+    // Collects the new lines of the templates and merges them into `current`,
+    // the content of `.gitignore` without its footer, if it exists. Returns
+    // the new content, `None` if it doesn't change.
+    let update = |current: Option<&str>| -> Result<Option<String>> {
+        let mut existing_lines = known_lines;
+        // What the generated sections of .gitignore came from, for `replace`.
+        let mut generated = String::new();
+        match current {
+            Some(content) => {
+                // Everything is added with `append`, and the generated sections
+                // are dropped before merging with `replace`.
+                let kept = match merge_strategy {
+                    MergeStrategy::Union => content.to_string(),
+                    MergeStrategy::Append => String::new(),
+                    MergeStrategy::Replace => {
+                        generated = fetched_section_templates(fetched, content, banner, options);
+                        merge::without_sections(content, banner, &generated)
+                    }
+                };
+                for line in kept.lines() {
+                    existing_lines.insert(trim_pattern(line).to_string());
+                }
+                if debug {
+                    eprintln!(
+                        "DEBUG: Loaded {} lines from existing .gitignore.",
+                        existing_lines.len()
+                    );
+                }
+            }
+            None if debug && write_to_file_flag && force => {
+                eprintln!("DEBUG: Overwriting existing .gitignore (force active).");
+            }
+            None if debug => {
+                eprintln!("DEBUG: .gitignore not found, will be created if lines are added.");
+            }
+            None => {}
+        }
+
+        if smart_dedup {
+            let canonical: Vec<String> = existing_lines
+                .iter()
+                .map(|line| canonical_pattern(line))
+                .collect();
+            existing_lines.extend(canonical);
+        }
 
-            // Check if .gitignore needs a newline before appending
-            let current_content_for_newline_check = read_to_string(gitignore_path)?;
-            if !current_content_for_newline_check.is_empty()
-                && !current_content_for_newline_check.ends_with('\n')
+        for FetchedTemplate {
+            name: template_spec_original,
+            fetched_as: template_spec_for_url,
+            body,
+        } in fetched
+        {
+            let (new_lines, current_template_existed_lines) = collect_new_lines(
+                body,
+                &mut existing_lines,
+                smart_dedup,
+                // Blank lines are only kept when printing, merging normalizes them.
+                preserve_blanks && !write_to_file_flag && !show_diff,
+                verbose,
+            );
+            let current_template_new_lines_added_to_session = new_lines.len();
+            if show_added {
+                added_by_template.push((template_spec_original.clone(), new_lines.clone()));
+            }
+            session_lines_to_add.extend(new_lines);
+            overall_new_lines_count_for_session += current_template_new_lines_added_to_session;
+
+            if write_to_file_flag && current_template_new_lines_added_to_session > 0 {
+                // Message per template if writing to file and new lines were found for *this* template
+                println!(
+                    "Collected {} new line(s) from '{}' for current session.",
+                    current_template_new_lines_added_to_session,
+                    template_spec_original.cyan()
+                );
+            }
+
+            if current_template_new_lines_added_to_session == 0
+                && current_template_existed_lines > 0
+            {
+                // If the template had content (checked by body.is_empty() earlier)
+                // and no new lines were added, but some existed, this message is appropriate.
+                // The check for `body.is_empty()` at the beginning of the success block
+                // already handles the case for truly empty templates.
+                if verbose || write_to_file_flag {
+                    // Show this if writing or verbose
+                    println!(
+                        "All patterns from '{}' (fetched as '{}') already existed or were duplicates (template was not empty).",
+                        template_spec_original.cyan(),
+                        template_spec_for_url.cyan()
+                    );
+                }
+            }
+        }
+
+        let mut extended_banner = None;
+        if let Some(banner) = banner
+            && !session_lines_to_add.is_empty()
+        {
+            if append_header_once
+                && existing_lines
+                    .iter()
+                    .any(|line| data::is_banner_line(line, banner))
             {
                 if verbose {
                     eprintln!(
-                        "VERBOSE: Adding newline to end of {} before appending.",
-                        GITIGNORE_FILE_NAME.cyan()
+                        "VERBOSE: Existing content already has a banner, not adding another one."
                     );
                 }
-                writeln!(file)?;
+                // Separate the lines from the last section like its templates are.
+                session_lines_to_add.insert(0, String::new());
+                extended_banner = Some(banner);
+            } else {
+                let names: Vec<String> = fetched
+                    .iter()
+                    .map(|template| template.name.clone())
+                    .collect();
+                let header = ["".to_string(), data::render_banner(banner, &names)];
+                session_lines_to_add.splice(0..0, header);
             }
+        }
 
-            for line in &session_lines_to_add {
-                writeln!(file, "{}", line)?;
-            }
+        if session_lines_to_add.is_empty() {
+            return Ok(None);
+        }
+        let lines: String = session_lines_to_add
+            .iter()
+            .map(|line| format!("{line}\n"))
+            .collect();
+        let merged = if force {
+            Some(lines)
+        } else {
+            merge::merge(
+                current.unwrap_or_default(),
+                &lines,
+                merge_strategy,
+                banner,
+                &generated,
+                prepend,
+            )
+        };
+        // List the new templates in the banner of the section they land in.
+        Ok(match extended_banner {
+            Some(banner) if !prepend && !force => merged.map(|merged| {
+                let names: Vec<String> = fetched
+                    .iter()
+                    .map(|template| template.name.clone())
+                    .collect();
+                data::extend_last_banner(&merged, banner, &names)
+            }),
+            _ => merged,
+        })
+    };
 
+    if show_diff {
+        let current = match read_gitignore(gitignore_path) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(Error::Io {
+                    context: format!("Failed to read {target}"),
+                    source: e,
+                });
+            }
+        };
+        let without_footer = current
+            .as_deref()
+            .map(|content| footer.map_or(content, |footer| data::without_footer(content, footer)));
+        let new_content = match (update(without_footer)?, footer) {
+            // The commit of templates fetched directly isn't known.
+            (Some(content), Some(footer)) => Some(merge::with_footer(&content, footer, None)),
+            (new_content, _) => new_content,
+        };
+        print!(
+            "{}",
+            diff::gitignore_diff(
+                current.as_deref(),
+                new_content
+                    .as_deref()
+                    .or(current.as_deref())
+                    .unwrap_or_default()
+            )
+        );
+    } else if write_to_file_flag {
+        let write_options = WriteOptions {
+            verbose,
+            lock,
+            follow_symlinks,
+            force,
+            encoding: output_encoding,
+            footer,
+            // The commit of templates fetched directly isn't known.
+            commit: None,
+        };
+        let created = !gitignore_path.exists();
+        if write_gitignore(gitignore_path, &write_options, update)?.is_some() {
+            if verbose && created {
+                eprintln!("VERBOSE: Created {}.", target.cyan());
+            }
             println!(
-                "Total {} new line(s) appended to {}.",
+                "Total {} new line(s) {} {}.",
                 overall_new_lines_count_for_session,
-                GITIGNORE_FILE_NAME.cyan()
+                if force || merge_strategy == MergeStrategy::Replace {
+                    "written to"
+                } else if prepend {
+                    "prepended to"
+                } else {
+                    "appended to"
+                },
+                target.cyan()
             );
-        } else if !succeeded_templates_list.trim().is_empty() {
+            if show_added {
+                report_added_lines(&added_by_template);
+            }
+        } else if !fetched.is_empty() {
             println!(
                 "No new lines were added to {} from the processed templates.",
-                GITIGNORE_FILE_NAME.cyan()
+                target.cyan()
             );
         }
     } else {
+        update(None)?;
         // Write to stdout
+        if filter_stdin {
+            print!("{}", stdin_content);
+            if !stdin_content.is_empty() && !stdin_content.ends_with('\n') {
+                println!();
+            }
+        }
         if !session_lines_to_add.is_empty() {
-            for line in &session_lines_to_add {
-                println!("{}", line);
+            let mut output = session_lines_to_add.join("\n");
+            output.push('\n');
+            if bare_output {
+                output = bare(&output);
             }
-        } else if !succeeded_templates_list.trim().is_empty() && verbose {
+            print!("{}", format_output(&output, output_format));
+        } else if !fetched.is_empty() && verbose {
             eprintln!("No new lines to output to stdout from the processed templates.");
         }
     }
 
-    if verbose {
-        eprint!("\nVERBOSE: ");
+    Ok(!session_lines_to_add.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use attohttpc::StatusCode;
+    use url::Url;
+
+    use super::{
+        Core, FetchFailure, GIT_REPO_DIR_NAME, LAST_UPDATE_FILE_NAME, bare, canonical_pattern,
+        collect_new_lines, encode_new, format_output, git_exclude_path, http_cache_path,
+        lock_gitignore, output_with_timeout, proxy_settings, scope_patterns, stable_hash, tidy,
+        write_lock_path,
+    };
+    use crate::{
+        cli::{OutputEncoding, OutputFormat},
+        detector::Detectors,
+    };
+
+    fn existing(lines: &[&str]) -> HashSet<String> {
+        lines.iter().map(|line| line.to_string()).collect()
     }
-    if !succeeded_templates_list.trim().is_empty() {
-        println!(
-            "Successfully processed template(s): {}",
-            succeeded_templates_list.trim().green()
+
+    #[test]
+    fn adds_a_bom_only_for_utf8_bom() {
+        assert_eq!(encode_new("target/\n", OutputEncoding::Utf8), b"target/\n");
+        assert_eq!(
+            encode_new("target/\n", OutputEncoding::Utf8Bom),
+            b"\xEF\xBB\xBFtarget/\n"
         );
     }
-    if !failed_templates_list.trim().is_empty() {
-        eprintln!(
-            "{}: {}",
-            "Failed to fetch or process template(s)".red(),
-            failed_templates_list.trim().yellow()
+
+    #[test]
+    fn tidies_duplicates_and_blank_lines() {
+        let (tidied, duplicates) = tidy("\n/build\nbuild\n\n\n*.log\n/build\n\n", false);
+        assert_eq!(tidied, "/build\nbuild\n\n*.log\n");
+        assert_eq!(duplicates, 1);
+
+        let (tidied, duplicates) = tidy("/build/out\nbuild/out\n", true);
+        assert_eq!(tidied, "/build/out\n");
+        assert_eq!(duplicates, 1);
+
+        // The repeat ignores `keep.log` again after the negation.
+        let content = "*.log\n!keep.log\n*.log\n";
+        assert_eq!(tidy(content, false), (content.to_string(), 0));
+        let (tidied, duplicates) = tidy("*.log\n!keep.log\n!keep.log\n*.log\n*.log\n", false);
+        assert_eq!(tidied, "*.log\n!keep.log\n*.log\n");
+        assert_eq!(duplicates, 2);
+
+        // `foo\ ` matches `foo ` with the space, so it isn't the same as `foo`.
+        assert_eq!(
+            tidy("foo\\  \nfoo\n", false),
+            ("foo\\ \nfoo\n".to_string(), 0)
         );
     }
 
-    if debug {
-        eprintln!("DEBUG: fetch_and_append_github_templates normal exit");
+    #[test]
+    fn skips_existing_lines() {
+        let mut existing_lines = existing(&["*.log", "target/"]);
+        let (new_lines, existed) = collect_new_lines(
+            "*.log\n\ntarget/\ndebug/\n",
+            &mut existing_lines,
+            false,
+            false,
+            false,
+        );
+        assert_eq!(new_lines, vec!["debug/"]);
+        assert_eq!(existed, 2);
+        assert!(existing_lines.contains("debug/"));
+    }
+
+    #[test]
+    fn keeps_negation_after_new_pattern() {
+        let mut existing_lines = existing(&["!important.log"]);
+        let (new_lines, _) = collect_new_lines(
+            "*.log\n!important.log\n",
+            &mut existing_lines,
+            false,
+            false,
+            false,
+        );
+        assert_eq!(new_lines, vec!["*.log", "!important.log"]);
+    }
+
+    #[test]
+    fn skips_existing_negation_without_new_patterns() {
+        let mut existing_lines = existing(&["*.log", "!important.log"]);
+        let (new_lines, existed) = collect_new_lines(
+            "# Logs\n*.log\n!important.log\n",
+            &mut existing_lines,
+            false,
+            false,
+            false,
+        );
+        assert_eq!(new_lines, vec!["# Logs"]);
+        assert_eq!(existed, 2);
+    }
+
+    #[test]
+    fn preserves_single_blank_lines() {
+        let mut existing_lines = existing(&["target/"]);
+        let (new_lines, existed) = collect_new_lines(
+            "\n# Build\ntarget/\n\n\n# Logs\n*.log\n\n",
+            &mut existing_lines,
+            false,
+            true,
+            false,
+        );
+        assert_eq!(new_lines, vec!["# Build", "", "# Logs", "*.log"]);
+        assert_eq!(existed, 1);
+    }
+
+    #[test]
+    fn trims_trailing_whitespace() {
+        let mut existing_lines = existing(&["target/"]);
+        let (new_lines, existed) = collect_new_lines(
+            "target/  \n*.log\t\nfoo\\  \n",
+            &mut existing_lines,
+            false,
+            false,
+            false,
+        );
+        // An escaped trailing space is part of the pattern.
+        assert_eq!(new_lines, vec!["*.log", "foo\\ "]);
+        assert_eq!(existed, 1);
+    }
+
+    #[test]
+    fn canonicalizes_equivalent_patterns() {
+        assert_eq!(canonical_pattern("/foo/bar"), "foo/bar");
+        assert_eq!(canonical_pattern("/foo/bar/"), "foo/bar/");
+        assert_eq!(canonical_pattern("**/foo"), "foo");
+        assert_eq!(canonical_pattern("**/foo/"), "foo/");
+        assert_eq!(canonical_pattern("!**/foo"), "!foo");
+        // These match different paths.
+        assert_eq!(canonical_pattern("/foo/"), "/foo/");
+        assert_eq!(canonical_pattern("/foo"), "/foo");
+        assert_eq!(canonical_pattern("**/foo/bar"), "**/foo/bar");
+        assert_eq!(canonical_pattern("# /foo/bar"), "# /foo/bar");
+        assert_eq!(canonical_pattern("\\/foo/bar"), "\\/foo/bar");
+    }
+
+    #[test]
+    fn skips_equivalent_patterns_with_smart_dedup() {
+        let mut existing_lines = existing(&["/foo/bar", "foo/bar", "node_modules/"]);
+        let (new_lines, existed) = collect_new_lines(
+            "foo/bar\n**/node_modules/\n/node_modules/\n**/dist\ndist\n",
+            &mut existing_lines,
+            true,
+            false,
+            false,
+        );
+        assert_eq!(new_lines, vec!["/node_modules/", "**/dist"]);
+        assert_eq!(existed, 3);
+    }
+
+    #[test]
+    fn skips_the_proxy_for_no_proxy_hosts() {
+        let proxy = Url::parse("http://proxy.local:3128").unwrap();
+        let github = Url::parse("https://raw.githubusercontent.com/github/gitignore").unwrap();
+        let internal = Url::parse("https://git.corp.example/gitignore").unwrap();
+
+        let settings = proxy_settings(&proxy, None);
+        assert_eq!(settings.for_url(&github), Some(&proxy));
+        let settings = proxy_settings(&proxy, Some("localhost, .corp.example"));
+        assert_eq!(settings.for_url(&github), Some(&proxy));
+        assert_eq!(settings.for_url(&internal), None);
+        let settings = proxy_settings(&proxy, Some("*"));
+        assert_eq!(settings.for_url(&github), None);
+    }
+
+    #[test]
+    fn anchors_patterns_under_the_scope() {
+        let content =
+            "# Build output\ntarget/\n/dist\nsrc/gen/\n**/*.log\n!keep.log\n!/dist/keep\n\n";
+        assert_eq!(
+            scope_patterns(content, "/frontend/"),
+            "# Build output\n/frontend/**/target/\n/frontend/dist\n/frontend/src/gen/\n\
+             /frontend/**/*.log\n!/frontend/**/keep.log\n!/frontend/dist/keep\n\n"
+        );
+        assert_eq!(scope_patterns(content, ""), content);
+    }
+
+    #[test]
+    fn keeps_only_patterns_when_bare() {
+        let content = "\n\n### Rust ###\n# Generated by Cargo\ntarget/\n\n**/*.rs.bk\n\\#notes\n";
+        assert_eq!(bare(content), "target/\n**/*.rs.bk\n\\#notes\n");
+        assert_eq!(bare("  # indented\n  build/\n \t\n"), "  build/\n");
+    }
+
+    #[test]
+    fn wraps_output_in_a_fenced_block() {
+        let content = "\n\n### Rust ###\ntarget/\n";
+        assert_eq!(format_output(content, OutputFormat::Raw), content);
+        assert_eq!(
+            format_output(content, OutputFormat::Markdown),
+            "```gitignore\n### Rust ###\ntarget/\n```\n"
+        );
+    }
+
+    #[test]
+    fn finds_git_exclude_file() {
+        let repo = tempfile::TempDir::new().unwrap();
+        let nested = repo.path().join("a/b");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_eq!(git_exclude_path(&nested), None);
+
+        std::fs::create_dir_all(repo.path().join(".git/info")).unwrap();
+        assert_eq!(git_exclude_path(&nested), None);
+        let exclude = repo.path().join(".git/info/exclude");
+        std::fs::write(&exclude, "*.swp\n").unwrap();
+        assert_eq!(git_exclude_path(&nested), Some(exclude));
+
+        let worktree = repo.path().join("worktree");
+        std::fs::create_dir_all(repo.path().join("gitdirs/wt/info")).unwrap();
+        std::fs::write(repo.path().join("gitdirs/wt/info/exclude"), "").unwrap();
+        std::fs::create_dir_all(&worktree).unwrap();
+        std::fs::write(worktree.join(".git"), "gitdir: ../gitdirs/wt\n").unwrap();
+        assert_eq!(
+            git_exclude_path(&worktree),
+            Some(worktree.join("../gitdirs/wt/info/exclude"))
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn kills_commands_that_time_out() {
+        let output = output_with_timeout(
+            std::process::Command::new("echo").arg("hi"),
+            "echo",
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hi\n");
+
+        let start = std::time::Instant::now();
+        let err = output_with_timeout(
+            std::process::Command::new("sleep").arg("10"),
+            "sleep",
+            Duration::from_millis(100),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "'sleep' timed out after 100ms");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn uses_injected_cache_dir() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cache");
+        let core = Core::with_cache_dir(fixture, Detectors::default());
+        assert!(core.cache_exists());
+        assert!(core.cache_age().is_none());
+        assert_eq!(core.template_count(), (5, 1));
+
+        let empty = tempfile::TempDir::new().unwrap();
+        let core = Core::with_cache_dir(empty.path().to_path_buf(), Detectors::default());
+        assert!(!core.cache_exists());
+        assert_eq!(core.template_count(), (0, 0));
+    }
+
+    #[test]
+    fn falls_back_to_the_clone_for_the_cache_age() {
+        let cache = tempfile::TempDir::new().unwrap();
+        let git_dir = cache.path().join(GIT_REPO_DIR_NAME).join(".git");
+        std::fs::create_dir_all(&git_dir).unwrap();
+        let cloned = SystemTime::now() - Duration::from_secs(10 * 86_400);
+        std::fs::File::open(&git_dir)
+            .unwrap()
+            .set_modified(cloned)
+            .unwrap();
+        let core = Core::with_cache_dir(cache.path().to_path_buf(), Detectors::default());
+        assert_eq!(core.cache_age().map(|age| age.as_secs() / 86_400), Some(10));
+
+        std::fs::write(cache.path().join(LAST_UPDATE_FILE_NAME), "").unwrap();
+        assert_eq!(core.cache_age().map(|age| age.as_secs() / 86_400), Some(0));
+    }
+
+    #[test]
+    fn falls_back_only_when_upstream_is_unavailable() {
+        for status in [
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::TOO_MANY_REQUESTS,
+        ] {
+            let failure = FetchFailure::from_status(status);
+            assert!(matches!(failure, FetchFailure::Unavailable(_)));
+        }
+        for status in [StatusCode::NOT_FOUND, StatusCode::FORBIDDEN] {
+            let failure = FetchFailure::from_status(status);
+            assert!(matches!(failure, FetchFailure::Rejected(_)));
+        }
+    }
+
+    #[test]
+    fn caches_every_url_in_its_own_file() {
+        let base = "https://raw.githubusercontent.com/github/gitignore/main/";
+        assert_ne!(
+            http_cache_path(&format!("{base}Global/Foo.gitignore")),
+            http_cache_path(&format!("{base}Global_Foo.gitignore"))
+        );
+    }
+
+    #[test]
+    fn hashes_the_same_in_every_build() {
+        assert_eq!(stable_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(stable_hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn locks_gitignore_until_dropped() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(".gitignore");
+        let lock = lock_gitignore(&path).unwrap();
+
+        let other_path = dir.path().join("sub/../.gitignore");
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let lock_path = write_lock_path(&other_path).unwrap();
+        assert_eq!(lock_path, write_lock_path(&path).unwrap());
+        let other = std::fs::File::open(&lock_path).unwrap();
+        assert!(other.try_lock().is_err());
+
+        drop(lock);
+        assert!(other.try_lock().is_ok());
     }
-    Ok(())
 }
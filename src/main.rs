@@ -4,34 +4,598 @@
 mod cli;
 mod data;
 mod detector;
+mod diff;
+mod error;
 mod ignore;
+mod merge;
+#[cfg(feature = "self-update-check")]
+mod self_update;
 mod user_data;
+mod vendor;
 
 use std::{
     collections::HashSet,
-    fs::{File, OpenOptions},
-    io::{self, Write},
+    ffi::OsString,
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc,
+    time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{CommandFactory, Parser};
-use cli::{AliasCmd, Cli, Cmds, TemplateCmd, print_completion};
+use cli::{
+    AliasCmd, Cli, Cmds, ConfigCmd, DetectCmd, ListFormat, MergeStrategy, RuleCmd, Source,
+    TemplateCase, TemplateCmd, print_completion,
+};
 use colored::Colorize;
-use ignore::Core;
-use user_data::UserData;
+use detector::Detection;
+use ignore::{
+    Core, DEFAULT_CACHE_MAX_AGE_DAYS, FetchOptions, WriteOptions, Written, read_gitignore,
+    write_gitignore,
+};
+use merge::trim_pattern;
+use notify::{RecursiveMode, Watcher};
+use user_data::{CONFIG_DIR_OVERRIDE, TEMPLATE_CASE_OVERRIDE, TEMPLATE_DIR_OVERRIDE, UserData};
 
-use crate::{
-    data::{IgnoreData, get_templates, list},
-    ignore::cache_exists,
+use crate::data::{
+    CACHE_DIR_OVERRIDE, DEFAULT_BANNER, DEFAULT_FOOTER, GIT_REPO_DIR_OVERRIDE, IgnoreData,
+    get_templates, globals_last, is_banner_line, is_global, list, list_synonyms, outdated_sections,
+    unresolved_templates, without_footer,
 };
 
+/// Prints the detected templates next to the files that triggered them, with
+/// the columns aligned.
+fn print_detection_table(detections: &[Detection]) {
+    const TEMPLATE: &str = "TEMPLATE";
+    let width = detections
+        .iter()
+        .map(|detection| detection.template.len())
+        .chain([TEMPLATE.len()])
+        .max()
+        .unwrap_or_default();
+    println!("{}", format!("{TEMPLATE:width$}  TRIGGERED BY").bold());
+    for detection in detections {
+        let files = detection
+            .trigger_files
+            .iter()
+            .map(|file| file.to_string_lossy())
+            .collect::<Vec<_>>();
+        println!(
+            "{}  {}",
+            format!("{:width$}", detection.template).cyan(),
+            files.join(", ")
+        );
+    }
+}
+
+/// Expands `@path` arguments into the template names listed in that file, one
+/// per line. Blank lines and lines starting with `#` are ignored.
+fn expand_template_args(args: Vec<String>) -> Result<Vec<String>> {
+    let mut templates = Vec::new();
+
+    for arg in args {
+        let Some(path) = arg.strip_prefix('@') else {
+            templates.push(arg);
+            continue;
+        };
+
+        let path = match path.strip_prefix("~/") {
+            Some(rest) => etcetera::home_dir()?.join(rest),
+            None => PathBuf::from(path),
+        };
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read template list from {}", path.display()))?;
+        templates.extend(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from),
+        );
+    }
+
+    Ok(templates)
+}
+
+/// Expands template arguments containing `*` into the names of all matching
+/// templates, aliases and user templates, like `Global/*`. Warns about patterns
+/// that match nothing.
+fn expand_template_globs(
+    args: Vec<String>,
+    ignore_data: &IgnoreData,
+    no_global: bool,
+) -> Vec<String> {
+    let mut templates = Vec::new();
+
+    for arg in args {
+        if !arg.contains('*') {
+            templates.push(arg);
+            continue;
+        }
+
+        let mut matches = ignore_data.matching(&arg);
+        if no_global {
+            matches.retain(|name| !is_global(name));
+        }
+        if matches.is_empty() {
+            eprintln!("{}: No templates match {}", "Warning".yellow(), arg.bold());
+        }
+        templates.extend(matches);
+    }
+
+    templates
+}
+
+/// The footer to add to written `.gitignore` files, set by `--footer` or
+/// `footer_template` in the config.
+fn footer_template(opt: &Cli, user_data: &UserData) -> Option<String> {
+    if opt.no_footer || !(opt.footer || user_data.footer_template.is_some()) {
+        return None;
+    }
+    Some(
+        user_data
+            .footer_template
+            .clone()
+            .unwrap_or_else(|| DEFAULT_FOOTER.to_string()),
+    )
+}
+
+/// Guards against a glob or alias unexpectedly resolving to a huge number of
+/// templates, before anything is fetched or written.
+fn check_template_limit(count: usize, limit: usize) -> Result<(), error::Error> {
+    if count > limit {
+        return Err(error::Error::TooManyTemplates { count, limit });
+    }
+    Ok(())
+}
+
+/// Drops template names that were already given, keeping the first spelling
+/// of each. Case is only ignored with [`TemplateCase::Insensitive`], as names
+/// that only differ in case are different templates otherwise.
+fn dedup_templates(args: Vec<String>, template_case: TemplateCase) -> Vec<String> {
+    let same = |a: &str, b: &str| match template_case {
+        TemplateCase::Insensitive => a.eq_ignore_ascii_case(b),
+        TemplateCase::Exact => a == b,
+    };
+    let mut templates: Vec<String> = Vec::new();
+    for arg in args {
+        if !templates.iter().any(|t| same(t, &arg)) {
+            templates.push(arg);
+        }
+    }
+    templates
+}
+
+/// Shows `output` through `$PAGER` (`less` if unset) when stdout is a terminal.
+/// Returns `false` if it wasn't paged, because stdout isn't a terminal, `$PAGER`
+/// is empty or the pager couldn't be started.
+fn page(output: &str) -> bool {
+    if !io::stdout().is_terminal() {
+        return false;
+    }
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    run_pager(&pager, output)
+}
+
+/// Pipes `output` into `pager`, a program followed by its arguments separated
+/// by whitespace. Returns `false` if `pager` is empty or couldn't be started.
+fn run_pager(pager: &str, output: &str) -> bool {
+    let mut parts = pager.split_whitespace();
+    let Some(program) = parts.next() else {
+        return false;
+    };
+
+    let mut command = Command::new(program);
+    command.args(parts).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        // Keep colors, and don't page output that fits on one screen.
+        command.env("LESS", "FRX");
+    }
+    let Ok(mut child) = command.spawn() else {
+        return false;
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may be closed before reading everything, that's fine.
+        let _ = stdin.write_all(output.as_bytes());
+    }
+    let _ = child.wait();
+    true
+}
+
+/// The lines `new` has that `old` doesn't, grouped by the first of `templates`
+/// they come from, for `--show-added`. Generated lines like the banner aren't
+/// part of any template and are left out. With `scope`, the templates are
+/// compared as they are written, see [`ignore::scope_patterns`].
+fn added_by_template(
+    data: &IgnoreData,
+    templates: &[String],
+    old: &str,
+    new: &str,
+    scope: Option<&str>,
+) -> error::Result<Vec<(String, Vec<String>)>> {
+    let old: HashSet<&str> = old.lines().map(trim_pattern).collect();
+    let mut added: HashSet<&str> = new
+        .lines()
+        .map(trim_pattern)
+        .filter(|line| !old.contains(line))
+        .collect();
+    templates
+        .iter()
+        .map(|name| {
+            let contents = match data.get_user_template(name) {
+                Some(content) => vec![content],
+                None => {
+                    let mut contents = Vec::new();
+                    for key in data.template_keys(std::slice::from_ref(name)) {
+                        contents.extend(data.get_template(&key)?);
+                    }
+                    contents
+                }
+            };
+            let contents: Vec<String> = match scope {
+                Some(dir) => contents
+                    .iter()
+                    .map(|content| ignore::scope_patterns(content, dir))
+                    .collect(),
+                None => contents,
+            };
+            let lines = contents
+                .iter()
+                .flat_map(|content| content.lines())
+                .map(trim_pattern)
+                .filter(|line| added.remove(*line))
+                .map(String::from)
+                .collect();
+            Ok((name.clone(), lines))
+        })
+        .collect()
+}
+
+/// Whether `default_write` from the config turns on `--write`. Neither `--write`
+/// nor `--no-write` has to be passed, and only templates are printed otherwise,
+/// so the default doesn't apply to listing, diffing and the like.
+fn defaults_to_write(opt: &Cli, args: &[OsString]) -> Result<bool> {
+    if opt.write || opt.no_write || opt.list || (opt.templates.is_empty() && !opt.auto) {
+        return Ok(false);
+    }
+    if UserData::new()?.default_write != Some(true) {
+        return Ok(false);
+    }
+    // Lets clap decide whether `--write` could have been passed.
+    let with_write = args.iter().cloned().chain([OsString::from("--write")]);
+    Ok(Cli::try_parse_from(with_write).is_ok())
+}
+
+/// The command line arguments with the default arguments from `GIT_IGNORE_ARGS`
+/// inserted before the explicit ones, so those take precedence. The defaults
+/// aren't used for subcommands.
+fn args_with_defaults() -> Result<Vec<OsString>> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let Ok(defaults) = std::env::var("GIT_IGNORE_ARGS") else {
+        return Ok(args);
+    };
+
+    let is_subcommand = args
+        .get(1)
+        .and_then(|arg| arg.to_str())
+        .is_some_and(|arg| Cli::command().find_subcommand(arg).is_some());
+    if !is_subcommand {
+        let defaults = shell_words::split(&defaults).context("Failed to parse GIT_IGNORE_ARGS")?;
+        args.splice(1..1, defaults.into_iter().map(OsString::from));
+    }
+    Ok(args)
+}
+
+/// How long to wait for more file system events before detecting templates again.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// The content the sections of `current` generated with `banner` came from,
+/// for `--merge-strategy replace`, see [`merge::without_sections`]. That is
+/// what their templates have now and had before, see [`Core::template_history`].
+fn section_templates(
+    app: &Core,
+    data: &IgnoreData,
+    current: &str,
+    banner: Option<&str>,
+    opt: &Cli,
+) -> Result<String> {
+    let Some(banner) = banner.filter(|_| opt.merge_strategy == MergeStrategy::Replace) else {
+        return Ok(String::new());
+    };
+    let mut templates = data::section_templates(data, current, banner)?;
+    templates.push_str(&app.template_history(&data::section_names(current, banner)));
+    Ok(match &opt.scope {
+        Some(dir) => ignore::scope_patterns(&templates, dir),
+        None => templates,
+    })
+}
+
+/// The files whose lines are skipped, from `--against` and `--respect-git-exclude`.
+fn against_files(opt: &Cli) -> Result<Vec<PathBuf>> {
+    let mut against = opt.against.clone();
+    if opt.respect_git_exclude {
+        match ignore::git_exclude_path(&std::env::current_dir()?) {
+            Some(path) => against.push(path),
+            None if opt.verbose => {
+                eprintln!("VERBOSE: No .git/info/exclude found, nothing to skip.");
+            }
+            None => {}
+        }
+    }
+    Ok(against)
+}
+
+/// How `.gitignore` files are written with `opt`, see [`ignore::write_gitignore`].
+fn write_options<'a>(
+    opt: &Cli,
+    footer: Option<&'a str>,
+    commit: Option<&'a str>,
+) -> WriteOptions<'a> {
+    WriteOptions {
+        verbose: opt.verbose,
+        lock: !opt.no_lock,
+        follow_symlinks: !opt.no_follow_symlinks,
+        force: opt.force,
+        encoding: opt.output_encoding,
+        footer,
+        commit,
+    }
+}
+
+/// Watches the current directory and adds the templates for newly detected
+/// project files to `.gitignore`, skipping the already `known` templates. Only
+/// the top level of the directory is watched, as that is what autodetection scans.
+fn watch(
+    app: &Core,
+    ignore_data: &IgnoreData,
+    banner: Option<&str>,
+    footer: Option<&str>,
+    opt: &Cli,
+    mut known: Vec<String>,
+) -> Result<()> {
+    let commit = footer
+        .and_then(|_| app.cache_commit_summary())
+        .map(|(hash, _)| hash);
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(sender).context("Failed to start the file watcher")?;
+    watcher
+        .watch(Path::new("."), RecursiveMode::NonRecursive)
+        .context("Failed to watch the current directory")?;
+    eprintln!(
+        "{}: Watching for new project files, press Ctrl-C to stop.",
+        "Info".bold().green()
+    );
+
+    while let Ok(event) = receiver.recv() {
+        event.context("Failed to watch the current directory")?;
+        // Changes usually come in bursts, wait for them to settle.
+        while receiver.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+
+        let added = add_detected_templates(
+            app,
+            Path::new("."),
+            &mut known,
+            opt,
+            banner,
+            &write_options(opt, footer, commit.as_deref()),
+            |templates| Ok(get_templates(ignore_data, templates, banner, false, opt.no_global)?.0),
+        )?;
+        if !added.is_empty() {
+            println!(
+                "{} content from local cache to {} for: {}",
+                if opt.prepend { "Prepended" } else { "Appended" },
+                ".gitignore".cyan(),
+                added.join(", ").green()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// One round of `--watch`: autodetects the templates in `dir` that aren't
+/// `known` yet, and merges what `render` returns for them into its
+/// `.gitignore`. Returns the templates that changed the file, all the detected
+/// ones are known afterwards.
+fn add_detected_templates(
+    app: &Core,
+    dir: &Path,
+    known: &mut Vec<String>,
+    opt: &Cli,
+    banner: Option<&str>,
+    options: &WriteOptions<'_>,
+    render: impl FnOnce(&[String]) -> Result<String>,
+) -> Result<Vec<String>> {
+    // Only templates that weren't there yet are added, so there is nothing to
+    // replace, and the generated sections from before have to stay.
+    let strategy = match opt.merge_strategy {
+        MergeStrategy::Replace => MergeStrategy::Union,
+        strategy => strategy,
+    };
+    let new_templates: Vec<String> = app
+        .autodetect_templates_in(dir)?
+        .into_iter()
+        .filter(|template| !known.contains(template))
+        .collect();
+    if new_templates.is_empty() {
+        return Ok(new_templates);
+    }
+    known.extend(new_templates.iter().cloned());
+
+    let output = render(&new_templates)?;
+    let options = WriteOptions {
+        force: false,
+        ..*options
+    };
+    let written = write_gitignore(&dir.join(".gitignore"), &options, |current| {
+        Ok::<_, anyhow::Error>(merge::merge(
+            current.unwrap_or_default(),
+            &output,
+            strategy,
+            banner,
+            "",
+            opt.prepend,
+        ))
+    })?;
+    Ok(match written {
+        Some(_) => new_templates,
+        None => Vec::new(),
+    })
+}
+
+/// Removes duplicate lines and extra blank lines from the `.gitignore` in the
+/// current directory, see [`ignore::tidy`].
+fn tidy(opt: &Cli) -> Result<()> {
+    let options = WriteOptions {
+        force: false,
+        ..write_options(opt, None, None)
+    };
+    let mut duplicates = 0;
+    let written = write_gitignore(Path::new(".gitignore"), &options, |content| {
+        let Some(content) = content else {
+            anyhow::bail!("There is no .gitignore in the current directory to tidy")
+        };
+        let (tidied, removed) = ignore::tidy(content, opt.smart_dedup);
+        duplicates = removed;
+        Ok((tidied != content).then_some(tidied))
+    })?;
+    if written.is_none() {
+        eprintln!(
+            "{}: '.gitignore' is already tidy, nothing was changed.",
+            "Info".bold().green()
+        );
+        return Ok(());
+    }
+    println!(
+        "Removed {duplicates} duplicate line(s) from {}",
+        ".gitignore".cyan()
+    );
+    Ok(())
+}
+
+/// Autodetects the templates of each immediate subdirectory of the current
+/// directory and merges them into a `.gitignore` in that subdirectory, like
+/// `--auto --write` does for the current directory. Hidden directories such as
+/// `.git` are skipped.
+fn write_per_directory(
+    app: &Core,
+    ignore_data: &IgnoreData,
+    banner: Option<&str>,
+    footer: Option<&str>,
+    opt: &Cli,
+) -> Result<()> {
+    let commit = footer
+        .and_then(|_| app.cache_commit_summary())
+        .map(|(hash, _)| hash);
+    let mut dirs = std::fs::read_dir(".")
+        .context("Failed to read the current directory")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| PathBuf::from(entry.file_name()))
+        .filter(|path| !path.to_string_lossy().starts_with('.'))
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    dirs.sort();
+
+    let mut detected_any = false;
+    for dir in dirs {
+        let mut templates = app.autodetect_templates_in(&dir)?;
+        if templates.is_empty() {
+            continue;
+        }
+        detected_any = true;
+        if !opt.preserve_order {
+            globals_last(&mut templates);
+        }
+        let (output, failed) = get_templates(
+            ignore_data,
+            &templates,
+            banner,
+            opt.flatten_global,
+            opt.no_global,
+        )?;
+        ignore::report_failed_templates(&failed);
+
+        let path = dir.join(".gitignore");
+        let written = write_gitignore(
+            &path,
+            &write_options(opt, footer, commit.as_deref()),
+            |current| -> Result<_> {
+                let Some(current) = current else {
+                    return Ok(Some(output.clone()).filter(|output| !output.is_empty()));
+                };
+                let generated = section_templates(app, ignore_data, current, banner, opt)?;
+                Ok(merge::merge(
+                    current,
+                    &output,
+                    opt.merge_strategy,
+                    banner,
+                    &generated,
+                    opt.prepend,
+                ))
+            },
+        )?;
+        let status = match written {
+            Some(_) => "",
+            None => " (already up to date)",
+        };
+        println!(
+            "{}: {}{}",
+            path.display().to_string().cyan(),
+            templates.join(", ").green(),
+            status
+        );
+    }
+
+    if !detected_any {
+        eprintln!("{}", "No templates detected in any subdirectory".blue());
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
-    let opt = Cli::parse();
+    let args = args_with_defaults()?;
+    let mut opt = Cli::parse_from(&args);
 
     if opt.debug {
         eprintln!("DEBUG: Parsed CLI options: {:?}", opt);
     }
 
+    // These must be set before anything resolves the cache or config paths.
+    if let Some(dir) = opt.cache_dir.clone() {
+        let _ = CACHE_DIR_OVERRIDE.set(dir);
+    }
+    if let Some(dir) = opt.config_dir.clone() {
+        let _ = CONFIG_DIR_OVERRIDE.set(dir);
+    }
+    if let Some(dir) = opt.template_dir.clone() {
+        let _ = TEMPLATE_DIR_OVERRIDE.set(dir);
+    }
+    if opt.repo_path.is_none() && opt.from_vendored.is_none() {
+        // A broken config is reported by whatever reads it next.
+        opt.repo_path = UserData::new()
+            .ok()
+            .and_then(|user_data| user_data.repo_path);
+    }
+    if let Some(dir) = opt.from_vendored.clone() {
+        let _ = GIT_REPO_DIR_OVERRIDE.set(dir);
+    } else if let Some(dir) = opt.repo_path.clone() {
+        data::check_repo_path(&dir)?;
+        let _ = GIT_REPO_DIR_OVERRIDE.set(dir);
+    }
+    if let Some(case) = opt.template_case {
+        let _ = TEMPLATE_CASE_OVERRIDE.set(case);
+    }
+    if let Some(proxy) = &opt.proxy {
+        let proxy =
+            url::Url::parse(proxy).with_context(|| format!("Invalid proxy URL '{proxy}'"))?;
+        let _ = ignore::PROXY_OVERRIDE.set(proxy);
+    }
+
+    opt.templates = expand_template_args(opt.templates)?;
+
     // Handle subcommands first
     if let Some(cmd) = opt.cmd {
         if opt.debug {
@@ -39,31 +603,147 @@ fn main() -> Result<()> {
         }
         // Initialize UserData and IgnoreData only if needed by a subcommand
         match cmd {
-            Cmds::Init { force } => return UserData::create(force),
+            Cmds::Config(ConfigCmd::Validate) => return Ok(UserData::validate()?),
+            Cmds::Config(ConfigCmd::Show { json }) => return Ok(UserData::show(json)?),
+            Cmds::Init { force, from } => return Ok(UserData::create(force, from.as_deref())?),
             Cmds::Alias(alias_cmd) => {
                 let mut user_data = UserData::new()?;
                 let ignore_data = IgnoreData::new(&user_data)?;
-                return match alias_cmd {
-                    AliasCmd::List => {
-                        ignore_data.list_aliases();
+                return Ok(match alias_cmd {
+                    AliasCmd::List { json: true, .. } => {
+                        ignore_data.print_aliases_json();
                         Ok(())
                     }
-                    AliasCmd::Add { name, aliases } => user_data.add_alias(name, aliases),
-                    AliasCmd::Remove { name } => user_data.remove_alias(&name),
-                };
+                    AliasCmd::List { expanded, .. } => {
+                        ignore_data.list_aliases(expanded);
+                        Ok(())
+                    }
+                    AliasCmd::Show { name } => {
+                        ignore_data.show_alias(&name);
+                        Ok(())
+                    }
+                    AliasCmd::Add {
+                        name,
+                        aliases,
+                        append,
+                        create,
+                        dry_run,
+                    } => {
+                        user_data.dry_run = dry_run;
+                        let aliases = if aliases == ["-"] {
+                            let input = io::read_to_string(io::stdin())
+                                .context("Failed to read alias targets from stdin")?;
+                            let aliases: Vec<String> =
+                                input.split_whitespace().map(String::from).collect();
+                            if aliases.is_empty() {
+                                anyhow::bail!("No alias targets were given on stdin");
+                            }
+                            aliases
+                        } else {
+                            aliases
+                        };
+                        if append {
+                            user_data.append_alias(name, aliases, create)
+                        } else {
+                            user_data.add_alias(name, aliases)
+                        }
+                    }
+                    AliasCmd::Remove { name, dry_run } => {
+                        user_data.dry_run = dry_run;
+                        user_data.remove_alias(&name)
+                    }
+                }?);
             }
             Cmds::Template(template_cmd) => {
                 let mut user_data = UserData::new()?;
                 let ignore_data = IgnoreData::new(&user_data)?;
-                return match template_cmd {
+                return Ok(match template_cmd {
                     TemplateCmd::List => {
                         ignore_data.list_templates();
                         Ok(())
                     }
-                    TemplateCmd::Add { name } => user_data.add_template(name),
-                    TemplateCmd::Remove { name } => user_data.remove_template(&name),
-                };
+                    TemplateCmd::Add {
+                        name,
+                        from,
+                        content,
+                        content_file,
+                        global,
+                        dry_run,
+                    } => {
+                        user_data.dry_run = dry_run;
+                        let content = if let Some(from) = from {
+                            Some(ignore_data.get_template(&from)?.with_context(|| {
+                                format!("No template named {from} found to copy from")
+                            })?)
+                        } else if let Some(path) = content_file {
+                            Some(std::fs::read_to_string(&path).with_context(|| {
+                                format!("Failed to read template content from {}", path.display())
+                            })?)
+                        } else if content.as_deref() == Some("-") {
+                            Some(
+                                io::read_to_string(io::stdin())
+                                    .context("Failed to read template content from stdin")?,
+                            )
+                        } else {
+                            content
+                        };
+                        if global {
+                            user_data.add_shared_template(&name, content)
+                        } else {
+                            user_data.add_template(name, content)
+                        }
+                    }
+                    TemplateCmd::Remove { name, dry_run } => {
+                        user_data.dry_run = dry_run;
+                        user_data.remove_template(&name)
+                    }
+                }?);
+            }
+            Cmds::Detect {
+                cmd: Some(DetectCmd::Rule(rule_cmd)),
+                ..
+            } => {
+                let mut user_data = UserData::new()?;
+                return Ok(match rule_cmd {
+                    RuleCmd::List => {
+                        user_data.list_detector_rules();
+                        Ok(())
+                    }
+                    RuleCmd::Add { pattern, template } => {
+                        user_data.add_detector_rule(pattern, template)
+                    }
+                    RuleCmd::Remove { pattern } => user_data.remove_detector_rule(&pattern),
+                }?);
             }
+            Cmds::Detect { dry_run, cmd: None } => {
+                let app = Core::new(&UserData::new()?);
+                let detections = app.autodetect()?;
+                if detections.is_empty() {
+                    eprintln!("{}", "No templates detected".blue());
+                } else if dry_run {
+                    print_detection_table(&detections);
+                    return Ok(());
+                }
+                for detection in detections {
+                    if opt.verbose {
+                        let files = detection
+                            .trigger_files
+                            .iter()
+                            .map(|file| file.to_string_lossy())
+                            .collect::<Vec<_>>();
+                        println!(
+                            "{} (triggered by: {})",
+                            detection.template.cyan(),
+                            files.join(", ")
+                        );
+                    } else {
+                        println!("{}", detection.template);
+                    }
+                }
+                return Ok(());
+            }
+            #[cfg(feature = "self-update-check")]
+            Cmds::SelfUpdateCheck => return Ok(self_update::check()?),
             Cmds::Completion { shell } => {
                 let mut app_cmd = Cli::command();
                 print_completion(shell, &mut app_cmd);
@@ -72,68 +752,337 @@ fn main() -> Result<()> {
         }
     }
 
-    // If no subcommand, and templates are provided directly, and it's not a list/update/auto for gitignore.io
-    if !opt.templates.is_empty() && !opt.list && !opt.update && !opt.auto {
+    if defaults_to_write(&opt, &args)? {
+        if opt.debug {
+            eprintln!("DEBUG: Writing to .gitignore because default_write is set.");
+        }
+        opt.write = true;
+    }
+    if !opt.write && (!opt.output.is_empty() || (opt.force && opt.combine_into.is_none())) {
+        opt.write = true;
+    }
+
+    if opt.print_cache_commit {
+        Core::new(&UserData::new()?).print_cache_commit();
+        return Ok(());
+    }
+
+    if opt.check_updates {
+        Core::new(&UserData::new()?).check_updates(Duration::from_secs(opt.git_timeout))?;
+        return Ok(());
+    }
+
+    if opt.tidy {
+        return tidy(&opt);
+    }
+
+    if opt.print_url {
+        let template_case = UserData::new()?.template_case();
+        ignore::print_template_urls(&opt.templates, opt.source, template_case, opt.debug);
+        return Ok(());
+    }
+
+    if opt.templates.iter().any(|template| template.contains('*')) {
+        let ignore_data = IgnoreData::new(&UserData::new()?)?;
+        opt.templates = expand_template_globs(opt.templates, &ignore_data, opt.no_global);
+        if opt.templates.is_empty() && !opt.auto {
+            return Ok(());
+        }
+    }
+    opt.templates = dedup_templates(opt.templates, UserData::new()?.template_case());
+    if opt.no_global {
+        opt.templates.retain(|template| {
+            let global = is_global(template);
+            if global {
+                eprintln!(
+                    "{}: Skipping {} because of --no-global",
+                    "Warning".yellow(),
+                    template.bold()
+                );
+            }
+            !global
+        });
+    }
+
+    if let Some(name) = &opt.explain {
+        let user_data = UserData::new()?;
+        return Ok(IgnoreData::new(&user_data)?.explain(name, &user_data, opt.source)?);
+    }
+
+    check_template_limit(opt.templates.len(), opt.limit_templates)?;
+    if opt.write {
+        ignore::ensure_in_git_repo(&std::env::current_dir()?, opt.require_git_repo, opt.quiet)?;
+    }
+
+    // If no subcommand, and templates are provided directly, and it's not a list/update/auto using the local cache
+    if !opt.templates.is_empty()
+        && !opt.list
+        && !opt.update
+        && !opt.auto
+        && !opt.resolve_only
+        && opt.combine_into.is_none()
+        && opt.vendor.is_none()
+        && opt.from_vendored.is_none()
+    {
         if opt.debug {
             eprintln!("DEBUG: Entering direct GitHub template fetch mode.");
         }
-        return ignore::fetch_and_append_github_templates(
+        let against = against_files(&opt)?;
+        let user_data = UserData::new()?;
+        // gitignore.io adds a header of its own.
+        let banner = (!opt.no_banner && !opt.bare && opt.source == Source::Github).then(|| {
+            user_data
+                .banner_template
+                .clone()
+                .unwrap_or_else(|| DEFAULT_BANNER.to_string())
+        });
+        let footer = footer_template(&opt, &user_data);
+        if !opt.preserve_order {
+            globals_last(&mut opt.templates);
+        }
+        let options = FetchOptions {
+            verbose: opt.verbose,
+            debug: opt.debug,
+            write: opt.write,
+            paths: &opt.output,
+            force: opt.force,
+            prepend: opt.prepend,
+            merge_strategy: opt.merge_strategy,
+            template_case: user_data.template_case(),
+            namespace: opt.namespace.as_deref(),
+            output_format: opt.output_format,
+            smart_dedup: opt.smart_dedup,
+            preserve_blanks: opt.preserve_blanks,
+            bare: opt.bare,
+            scope: opt.scope.as_deref(),
+            show_added: opt.show_added,
+            fail_on_empty: opt.fail_on_empty,
+            filter: opt.filter,
+            strict: opt.strict,
+            no_cache: opt.no_cache,
+            source: opt.source,
+            lock: !opt.no_lock,
+            output_encoding: opt.output_encoding,
+            follow_symlinks: !opt.no_follow_symlinks,
+            banner: banner.as_deref(),
+            footer: footer.as_deref(),
+            append_header_once: opt.append_header_once,
+            diff: opt.diff,
+            against: &against,
+        };
+        return Ok(ignore::fetch_and_append_github_templates(
             &opt.templates,
-            opt.verbose,
-            opt.debug,
-            opt.write,
-        );
+            &options,
+        )?);
     }
 
-    // --- Existing logic for gitignore.io cache, list, auto, etc. ---
+    // --- Logic using the local github/gitignore cache: list, auto, etc. ---
     if opt.debug {
-        eprintln!("DEBUG: Entering gitignore.io cache logic mode.");
+        eprintln!("DEBUG: Entering local cache logic mode.");
     }
 
-    let app = Core::new();
-    let user_data = UserData::new()?; // Removed mut, as it's not mutated in this path
+    let mut user_data = UserData::new()?;
+    let app = Core::new(&user_data);
     let ignore_data = IgnoreData::new(&user_data)?;
+    if opt.verbose {
+        eprintln!("VERBOSE: {}", ignore_data.summary());
+        ignore_data.warn_shadowed();
+    }
 
-    if opt.update {
+    if let Some(dir) = &opt.repo_path {
+        if opt.update {
+            // Only possible with `repo_path` from the config, `--repo-path` conflicts with `-u`.
+            eprintln!(
+                "{}: Templates are read from the checkout at {}, update it with git instead.",
+                "Info".bold().green(),
+                dir.display()
+            );
+            if opt.templates.is_empty() && !opt.auto && !opt.list && !opt.verify {
+                return Ok(());
+            }
+        } else if opt.verbose {
+            eprintln!(
+                "VERBOSE: Reading templates from the checkout at {}",
+                dir.display()
+            );
+        }
+    } else if opt.update {
         if opt.verbose {
             eprintln!("VERBOSE: Updating local github/gitignore repository cache...");
         }
-        app.update()?; // This will now print its own success/failure messages.
-        if opt.templates.is_empty() && !opt.auto && !opt.list {
+        app.update(opt.no_wait, opt.quiet, Duration::from_secs(opt.git_timeout))?; // This will now print its own success/failure messages.
+        if opt.templates.is_empty() && !opt.auto && !opt.list && !opt.verify {
             if opt.debug {
-                eprintln!("DEBUG: Local repository cache update process finished, no further templates to process. Exiting.");
+                eprintln!(
+                    "DEBUG: Local repository cache update process finished, no further templates to process. Exiting."
+                );
             }
             return Ok(());
         }
-    } else if cache_exists() {
+    } else if let Some(dir) = &opt.from_vendored {
+        if opt.verbose {
+            eprintln!("VERBOSE: Reading templates vendored in {}", dir.display());
+        }
+    } else if app.cache_exists() {
         if opt.verbose || (!opt.list && !opt.templates.is_empty()) {
             eprintln!(
                 "{}: You are using the local github/gitignore repository cache, pass '-u' to update it.\n",
                 "Info".bold().green(),
             );
         }
-    } else if !opt.list { // If not listing and cache doesn't exist, try to update (clone) it.
+        let max_age = opt
+            .max_age
+            .or(user_data.cache_max_age)
+            .unwrap_or(DEFAULT_CACHE_MAX_AGE_DAYS);
+        if !opt.quiet
+            && let Some(age) = app.cache_age()
+        {
+            let days = age.as_secs() / 86_400;
+            if days > max_age {
+                eprintln!(
+                    "{}: Cache is {} days old, run -u to refresh.",
+                    "Warning".bold().red(),
+                    days
+                );
+            }
+        }
+    } else if !opt.list {
+        // If not listing and cache doesn't exist, try to update (clone) it. Listing
+        // must never touch the network, it just shows what's available locally.
         eprintln!(
             "{}: Local github/gitignore repository cache not found, attempting to clone/update.",
             "Warning".bold().red(),
         );
-        app.update()?; // This will attempt to clone.
+        app.update(opt.no_wait, opt.quiet, Duration::from_secs(opt.git_timeout))?; // This will attempt to clone.
     }
 
-    let mut all_templates_for_cache: HashSet<String> = opt.templates.into_iter().collect();
+    if opt.verify {
+        let banner = user_data
+            .banner_template
+            .as_deref()
+            .unwrap_or(DEFAULT_BANNER);
+        let content =
+            read_gitignore(Path::new(".gitignore")).context("Failed to read .gitignore")?;
+        let footer = user_data
+            .footer_template
+            .as_deref()
+            .unwrap_or(DEFAULT_FOOTER);
+        if let Some(dir) = &opt.from_vendored {
+            let names = data::section_names(&content, banner);
+            vendor::check_vendored(&ignore_data, &names, dir)?;
+        }
+        let outdated = outdated_sections(&ignore_data, &content, banner, footer, |names| {
+            app.template_history(names)
+        })?;
+        if outdated.is_empty() {
+            println!("{} is up to date with its templates", ".gitignore".cyan());
+            return Ok(());
+        }
+
+        for section in &outdated {
+            println!(
+                "Section for {} is out of date:",
+                section.templates.join(", ").yellow()
+            );
+            for name in &section.unknown {
+                println!("  {}", format!("unknown template {name}").red());
+            }
+            for line in section.diff.lines() {
+                if line.starts_with("+++") || line.starts_with("---") {
+                    println!("{}", line.bold());
+                } else if line.starts_with('+') {
+                    println!("{}", line.green());
+                } else if line.starts_with('-') {
+                    println!("{}", line.red());
+                } else if line.starts_with("@@") {
+                    println!("{}", line.cyan());
+                } else {
+                    println!("{line}");
+                }
+            }
+        }
+        anyhow::bail!(
+            "{} section(s) of .gitignore are out of date",
+            outdated.len()
+        );
+    }
+
+    if let Some(namespace) = &opt.namespace {
+        opt.templates = opt
+            .templates
+            .iter()
+            .map(|template| ignore_data.namespaced(template, namespace))
+            .collect();
+    }
+    // Keep the order the templates were given in, they are already deduplicated.
+    let mut templates_for_cache = std::mem::take(&mut opt.templates);
     if opt.auto {
         if opt.verbose {
-            eprintln!("VERBOSE: Autodetecting templates using local github/gitignore repository cache...");
+            eprintln!(
+                "VERBOSE: Autodetecting templates using local github/gitignore repository cache..."
+            );
         }
         for template in app.autodetect_templates()? {
             if opt.verbose {
-                eprintln!("VERBOSE: Autodetected (from local cache): {}", template.cyan());
+                eprintln!(
+                    "VERBOSE: Autodetected (from local cache): {}",
+                    template.cyan()
+                );
             }
-            all_templates_for_cache.insert(template);
+            templates_for_cache.push(template);
+        }
+        if opt.no_global {
+            templates_for_cache.retain(|template| !is_global(template));
+        }
+        templates_for_cache = dedup_templates(templates_for_cache, user_data.template_case());
+    }
+
+    check_template_limit(
+        ignore_data.resolved_count(&templates_for_cache),
+        opt.limit_templates,
+    )?;
+    if !opt.preserve_order {
+        globals_last(&mut templates_for_cache);
+    }
+    if opt.resolve_only {
+        let (keys, unresolved) = ignore_data.resolve(&templates_for_cache, opt.no_global);
+        for key in keys {
+            println!("{key}");
         }
+        if !unresolved.is_empty() {
+            eprintln!(
+                "{}: Could not resolve: {}",
+                "Warning".yellow(),
+                unresolved.join(", ")
+            );
+        }
+        return Ok(());
     }
 
-    let templates_for_cache: Vec<String> = all_templates_for_cache.iter().cloned().collect();
+    let default_banner = (!opt.no_banner && !opt.bare).then(|| {
+        user_data
+            .banner_template
+            .as_deref()
+            .unwrap_or(DEFAULT_BANNER)
+    });
+    let footer = footer_template(&opt, &user_data);
+    let commit = footer
+        .as_ref()
+        .and_then(|_| app.cache_commit_summary())
+        .map(|(hash, _)| hash);
+    if opt.per_directory {
+        return write_per_directory(&app, &ignore_data, default_banner, footer.as_deref(), &opt);
+    }
+    if opt.watch && templates_for_cache.is_empty() {
+        return watch(
+            &app,
+            &ignore_data,
+            default_banner,
+            footer.as_deref(),
+            &opt,
+            templates_for_cache,
+        );
+    }
 
     if opt.update && templates_for_cache.is_empty() && !opt.list {
         if opt.debug {
@@ -144,6 +1093,59 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
+    if opt.strict && !opt.list {
+        let unresolved = unresolved_templates(&ignore_data, templates_for_cache.as_slice());
+        if !unresolved.is_empty() {
+            anyhow::bail!(
+                "Could not resolve template(s), nothing was written: {}",
+                unresolved.join(", ")
+            );
+        }
+    }
+
+    if let Some(dir) = &opt.from_vendored
+        && !opt.list
+    {
+        vendor::check_vendored(&ignore_data, &templates_for_cache, dir)?;
+    }
+
+    if let Some(dir) = &opt.vendor {
+        vendor::vendor_templates(&ignore_data, &templates_for_cache, dir, app.cache_commit())?;
+    }
+
+    if let Some(name) = opt.combine_into {
+        if user_data.templates.contains_key(&name) && !opt.force {
+            anyhow::bail!(
+                "A user template named {name} already exists, pass --force to replace it"
+            );
+        }
+        let (content, failed) = get_templates(
+            &ignore_data,
+            &templates_for_cache,
+            None,
+            false,
+            opt.no_global,
+        )?;
+        ignore::report_failed_templates(&failed);
+        if content.is_empty() {
+            anyhow::bail!("None of the templates could be resolved, {name} was not created");
+        }
+        return Ok(user_data.add_template(name, Some(content))?);
+    }
+
+    let mut failed_templates = Vec::new();
+    // With `--append-header-once`, the banner the new templates are listed in
+    // instead of getting one of their own.
+    let mut extended_banner = None;
+    if opt.list && opt.count_only {
+        let count = data::count(&ignore_data, &templates_for_cache, opt.only);
+        println!("{count}");
+        if count == 0 {
+            anyhow::bail!("No templates match");
+        }
+        return Ok(());
+    }
+
     let output_str = if opt.list {
         if opt.verbose {
             eprintln!(
@@ -151,12 +1153,31 @@ fn main() -> Result<()> {
                 templates_for_cache
             );
         }
-        list(&ignore_data, templates_for_cache.as_slice())
+        let mut output = if opt.tree {
+            data::tree(
+                &ignore_data,
+                templates_for_cache.as_slice(),
+                opt.only,
+                opt.verbose,
+            )
+        } else {
+            list(
+                &ignore_data,
+                templates_for_cache.as_slice(),
+                opt.format,
+                opt.only,
+                opt.verbose,
+            )
+        };
+        // Plain output is meant for scripts, which expect one name per line.
+        if opt.verbose && opt.only.is_none() && opt.format == ListFormat::Pretty {
+            output.push('\n');
+            output.push_str(&list_synonyms());
+        }
+        output
     } else if templates_for_cache.is_empty() {
         if opt.debug {
-            eprintln!(
-                "DEBUG: No templates specified for local cache processing, rendering help."
-            );
+            eprintln!("DEBUG: No templates specified for local cache processing, rendering help.");
         }
         let mut app_cmd = Cli::command();
         app_cmd.render_help().to_string()
@@ -167,72 +1188,306 @@ fn main() -> Result<()> {
                 templates_for_cache
             );
         }
-        get_templates(&ignore_data, templates_for_cache.as_slice())
+        let mut banner = default_banner;
+        if opt.append_header_once
+            && opt.write
+            && let Some(existing_banner) = banner
+            && let Ok(content) = read_gitignore(Path::new(".gitignore"))
+            && content
+                .lines()
+                .any(|line| is_banner_line(line, existing_banner))
+        {
+            if opt.verbose {
+                eprintln!("VERBOSE: '.gitignore' already has a banner, not adding another one.");
+            }
+            banner = None;
+            extended_banner = Some(existing_banner);
+        }
+        let (output, failed) = get_templates(
+            &ignore_data,
+            templates_for_cache.as_slice(),
+            banner,
+            opt.flatten_global,
+            opt.no_global,
+        )?;
+        failed_templates = failed;
+        let output = match &opt.scope {
+            Some(dir) => ignore::scope_patterns(&output, dir),
+            None => output,
+        };
+        ignore::without_lines_in(&output, &against_files(&opt)?)?
     };
 
+    // In filter mode the existing content comes from stdin, and is printed
+    // with the templates merged into it.
+    let mut stdin_content = String::new();
+    if opt.filter {
+        io::stdin()
+            .read_to_string(&mut stdin_content)
+            .context("Failed to read existing content from stdin")?;
+    }
+
     if output_str.is_empty() && templates_for_cache.is_empty() && !opt.list {
         // Help was rendered into output_str.
         if opt.debug {
             eprintln!("DEBUG: Output string is empty (help was rendered).");
         }
     } else if output_str.is_empty() && !templates_for_cache.is_empty() {
-        eprintln!(
-            "{}: No templates found in local github/gitignore repository cache for: {}",
-            "Warning".yellow(),
-            templates_for_cache.join(", ")
-        );
+        // Templates that exist but are empty have been warned about already.
+        if failed_templates.len() == templates_for_cache.len() {
+            eprintln!(
+                "{}: No templates found in local github/gitignore repository cache for: {}",
+                "Warning".yellow(),
+                templates_for_cache.join(", ")
+            );
+        } else {
+            ignore::report_failed_templates(&failed_templates);
+        }
+        if opt.fail_on_empty {
+            return Err(error::Error::NothingAdded.into());
+        }
+        print!("{stdin_content}");
         return Ok(());
+    } else {
+        ignore::report_failed_templates(&failed_templates);
     }
 
-    if opt.write {
+    let add_footer = |content: String| match &footer {
+        Some(footer) => merge::with_footer(&content, footer, commit.as_deref()),
+        None => content,
+    };
+    let without_footer = |current: &str| -> String {
+        footer
+            .as_deref()
+            .map_or(current, |footer| without_footer(current, footer))
+            .to_string()
+    };
+    // Merges into `current`, the content of `.gitignore` without its footer.
+    let merge = |current: &str| -> Result<Option<String>> {
+        let generated = section_templates(&app, &ignore_data, current, default_banner, &opt)?;
+        // Without a banner of their own, separate the templates from the last
+        // section like its templates are.
+        let output = match extended_banner {
+            Some(_) if !opt.prepend => format!("\n{output_str}"),
+            _ => output_str.clone(),
+        };
+        let merged = merge::merge(
+            current,
+            &output,
+            opt.merge_strategy,
+            default_banner,
+            &generated,
+            opt.prepend,
+        );
+        Ok(match extended_banner {
+            Some(banner) if !opt.prepend => merged.map(|merged| {
+                let names =
+                    data::banner_names(&templates_for_cache, opt.flatten_global, opt.no_global);
+                data::extend_last_banner(&merged, banner, &names)
+            }),
+            _ => merged,
+        })
+    };
+    if opt.diff {
+        let current = match read_gitignore(Path::new(".gitignore")) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(anyhow::Error::new(e).context("Failed to read .gitignore")),
+        };
+        // Shows the result of merging, like `--write` would.
+        let new_content = match current.as_deref() {
+            Some(current) => merge(&without_footer(current))?
+                .map(add_footer)
+                .unwrap_or_else(|| current.to_string()),
+            None => add_footer(output_str.clone()),
+        };
+        print!("{}", diff::gitignore_diff(current.as_deref(), &new_content));
+    } else if opt.filter {
+        if opt.debug {
+            eprintln!("DEBUG: Merging local cache output into stdin.");
+        }
+        let new_content = if stdin_content.is_empty() {
+            add_footer(output_str.clone())
+        } else {
+            merge(&without_footer(&stdin_content))?
+                .map(add_footer)
+                .unwrap_or_else(|| stdin_content.clone())
+        };
+        if new_content == stdin_content && opt.fail_on_empty {
+            return Err(error::Error::NothingAdded.into());
+        }
+        print!("{new_content}");
+    } else if opt.write {
         if opt.debug {
             eprintln!("DEBUG: Write flag is set for local cache output.");
         }
-        let file_path = std::env::current_dir()?.join(".gitignore");
-        if !file_path.exists() {
-            if opt.verbose {
-                eprintln!(
-                    "VERBOSE: no '.gitignore' file found, creating with content from local cache...",
-                );
-            }
-            let mut file = File::create(&file_path)?;
-            file.write_all(output_str.as_bytes())?;
-            println!(
-                "Created {} with content from local cache for: {}",
-                ".gitignore".cyan(),
-                templates_for_cache.join(", ").green()
-            );
-        } else if opt.force {
-            if opt.verbose {
-                eprintln!(
-                    "VERBOSE: appending results from local cache to '.gitignore' (force active)...",
-                );
-            }
-            let mut file = OpenOptions::new().append(true).open(&file_path)?;
-            let current_content = std::fs::read_to_string(&file_path)?;
-            if !current_content.is_empty() && !current_content.ends_with('\n') {
-                writeln!(file)?;
-            }
-            file.write_all(output_str.as_bytes())?;
-            println!(
-                "Appended content from local cache to {} for: {}",
-                ".gitignore".cyan(),
-                templates_for_cache.join(", ").green()
-            );
+        let targets = if opt.output.is_empty() {
+            vec![std::env::current_dir()?.join(".gitignore")]
         } else {
-            eprintln!(
-                "{}: '.gitignore' already exists. Use '-f' to append results from local cache, or handle manually.",
-                "Warning".bold().red()
-            );
+            opt.output.clone()
+        };
+        let options = write_options(&opt, footer.as_deref(), commit.as_deref());
+        // Every target is read and merged into on its own.
+        let mut added_nothing = false;
+        for file_path in &targets {
+            // The default target is shown by its name, like everywhere else.
+            let name = if opt.output.is_empty() {
+                ".gitignore".to_string()
+            } else {
+                file_path.display().to_string()
+            };
+            let written = write_gitignore(file_path, &options, |current| match current {
+                Some(current) => {
+                    if opt.verbose {
+                        eprintln!("VERBOSE: adding results from local cache to '{name}'...");
+                    }
+                    merge(current)
+                }
+                None => {
+                    if opt.verbose {
+                        eprintln!("VERBOSE: writing content from local cache to '{name}'...");
+                    }
+                    Ok(Some(output_str.clone()))
+                }
+            })?;
+            match written {
+                Some(Written { old: None, new }) => {
+                    println!(
+                        "{} {} with content from local cache for: {}",
+                        if opt.force { "Overwrote" } else { "Created" },
+                        name.cyan(),
+                        templates_for_cache.join(", ").green()
+                    );
+                    if opt.show_added {
+                        ignore::report_added_lines(&added_by_template(
+                            &ignore_data,
+                            &templates_for_cache,
+                            "",
+                            &new,
+                            opt.scope.as_deref(),
+                        )?);
+                    }
+                }
+                Some(Written {
+                    old: Some(old),
+                    new,
+                }) => {
+                    let action = match opt.merge_strategy {
+                        MergeStrategy::Replace => {
+                            "Replaced generated sections with content from local cache in"
+                        }
+                        _ if opt.prepend => "Prepended content from local cache to",
+                        _ => "Appended content from local cache to",
+                    };
+                    println!(
+                        "{action} {} for: {}",
+                        name.cyan(),
+                        templates_for_cache.join(", ").green()
+                    );
+                    if opt.show_added {
+                        ignore::report_added_lines(&added_by_template(
+                            &ignore_data,
+                            &templates_for_cache,
+                            &old,
+                            &new,
+                            opt.scope.as_deref(),
+                        )?);
+                    }
+                }
+                None => {
+                    eprintln!(
+                        "{}: '{name}' already contains the templates, nothing was added. Use '-f' to overwrite it.",
+                        "Info".bold().green()
+                    );
+                    added_nothing = true;
+                }
+            }
+        }
+        if added_nothing && opt.fail_on_empty {
+            return Err(error::Error::NothingAdded.into());
         }
     } else {
         if opt.debug {
             eprintln!("DEBUG: Writing local cache output to stdout.");
         }
+        if opt.list && page(&output_str) {
+            return Ok(());
+        }
         let stdout_handle = io::stdout();
         let mut locked_stdout = stdout_handle.lock();
+        let output_str = if opt.bare {
+            ignore::bare(&output_str)
+        } else {
+            output_str
+        };
+        let output_str = ignore::format_output(&output_str, opt.output_format);
         locked_stdout.write_all(output_str.as_bytes())?;
     }
 
+    if opt.watch {
+        return watch(
+            &app,
+            &ignore_data,
+            default_banner,
+            footer.as_deref(),
+            &opt,
+            templates_for_cache,
+        );
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use clap::Parser;
+
+    use super::{add_detected_templates, run_pager, write_options};
+    use crate::{cli::Cli, detector::Detectors, ignore::Core};
+
+    #[test]
+    fn adds_the_templates_of_new_project_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cache");
+        let app = Core::with_cache_dir(fixture, Detectors::default());
+        let opt = Cli::parse_from(["git-ignore", "--auto", "--watch", "--write", "--prepend"]);
+        let options = write_options(&opt, None, None);
+        let render = |templates: &[String]| Ok(format!("# {}\ntarget/\n", templates.join(", ")));
+        let gitignore = dir.path().join(".gitignore");
+        fs::write(&gitignore, "/local/\n").unwrap();
+        let mut known = vec!["node".to_string()];
+        let mut add = || {
+            add_detected_templates(&app, dir.path(), &mut known, &opt, None, &options, render)
+                .unwrap()
+        };
+
+        fs::write(dir.path().join("package.json"), "{}").unwrap();
+        assert!(add().is_empty());
+
+        fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        assert_eq!(add(), ["rust"]);
+        assert_eq!(
+            fs::read_to_string(&gitignore).unwrap(),
+            "# rust\ntarget/\n\n/local/\n"
+        );
+        // Templates are only added once, even if the file is changed by hand.
+        fs::write(&gitignore, "").unwrap();
+        assert!(add().is_empty());
+        assert_eq!(known, ["node", "rust"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn pipes_the_output_into_the_pager() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let paged = dir.path().join("paged");
+        let pager = format!("cp /dev/stdin {}", paged.display());
+        assert!(run_pager(&pager, "Rust\nNode\n"));
+        assert_eq!(fs::read_to_string(&paged).unwrap(), "Rust\nNode\n");
+
+        assert!(!run_pager("", "Rust\n"));
+        assert!(!run_pager("git-ignore-missing-pager", "Rust\n"));
+    }
+}
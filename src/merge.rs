@@ -0,0 +1,329 @@
+use std::collections::HashSet;
+
+use crate::{
+    cli::MergeStrategy,
+    data::{is_banner_line, render_footer, without_footer},
+};
+
+/// Merges the generated `new` content into the `current` content of
+/// `.gitignore` following `strategy`. With `prepend`, the new lines are
+/// inserted at the top instead of the end, see [`prepend_lines`]. The sections
+/// generated with `banner` are the ones `replace` overwrites, keeping the lines
+/// that aren't from `generated` or `new`, see [`without_sections`]. Returns
+/// `None` if the content wouldn't change.
+pub fn merge(
+    current: &str,
+    new: &str,
+    strategy: MergeStrategy,
+    banner: Option<&str>,
+    generated: &str,
+    prepend: bool,
+) -> Option<String> {
+    let (base, block) = match strategy {
+        MergeStrategy::Append if new.trim().is_empty() => return None,
+        MergeStrategy::Append => (current.to_string(), new.to_string()),
+        MergeStrategy::Union => (current.to_string(), new_lines(current, new)?),
+        MergeStrategy::Replace => {
+            let base = without_sections(current, banner, &format!("{generated}\n{new}"));
+            let mut block = new_lines(&base, new).unwrap_or_default();
+            // The kept lines and the new sections are set apart, like sections are.
+            if !base.is_empty() && !block.is_empty() && !block.starts_with('\n') && !prepend {
+                block.insert(0, '\n');
+            }
+            (base, block)
+        }
+    };
+
+    let content = if block.is_empty() {
+        base
+    } else if prepend {
+        prepend_lines(&base, &block)
+    } else {
+        let mut content = base;
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&block);
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content
+    };
+    (content != current).then_some(content)
+}
+
+/// Drops the trailing whitespace of a `.gitignore` line, which git ignores,
+/// except for a space escaped with a backslash, as in `foo\ `.
+pub fn trim_pattern(line: &str) -> &str {
+    let trimmed = line.trim_end();
+    let backslashes = trimmed.len() - trimmed.trim_end_matches('\\').len();
+    if backslashes % 2 == 1 && line[trimmed.len()..].starts_with(' ') {
+        &line[..=trimmed.len()]
+    } else {
+        trimmed
+    }
+}
+
+/// The lines of `new` that `current` doesn't contain yet, keeping single blank
+/// lines between them, or `None` if there are no new patterns.
+///
+/// Negations (`!pattern`) only have an effect after the patterns they negate,
+/// so once a new pattern is kept, later negations are kept even if they exist.
+pub fn new_lines(current: &str, new: &str) -> Option<String> {
+    let existing: HashSet<&str> = current.lines().map(trim_pattern).collect();
+    let mut added_pattern = false;
+    let mut lines = Vec::new();
+    for line in new.lines().map(trim_pattern) {
+        let is_negation = line.starts_with('!');
+        if line.is_empty() || !existing.contains(line) || (is_negation && added_pattern) {
+            if !line.is_empty() && !is_negation && !line.starts_with('#') {
+                added_pattern = true;
+            }
+            lines.push(line);
+        }
+    }
+    if lines
+        .iter()
+        .all(|line| line.is_empty() || line.starts_with('#'))
+    {
+        return None;
+    }
+    lines.dedup_by(|a, b| a.is_empty() && b.is_empty());
+    Some(lines.join("\n") + "\n")
+}
+
+/// Removes the sections generated with `banner` from `content`. Like for
+/// `--verify`, a section starts at a banner line and runs until the next
+/// banner or the end of the file. The lines of a section that aren't in
+/// `generated`, the content of its templates, were added by hand and are kept,
+/// after everything in front of the first banner.
+pub fn without_sections(content: &str, banner: Option<&str>, generated: &str) -> String {
+    let Some(banner) = banner else {
+        return content.to_string();
+    };
+    let generated: HashSet<&str> = generated.lines().map(trim_pattern).collect();
+    let mut kept = String::new();
+    let mut added_by_hand = Vec::new();
+    let mut in_section = false;
+    for line in content.split_inclusive('\n') {
+        let trimmed = trim_pattern(line);
+        if is_banner_line(trimmed, banner) {
+            in_section = true;
+        } else if !in_section {
+            kept.push_str(line);
+        } else if !trimmed.is_empty() && !generated.contains(trimmed) {
+            added_by_hand.push(trimmed);
+        }
+    }
+    let mut kept = kept.trim_end().to_string();
+    if !added_by_hand.is_empty() {
+        if !kept.is_empty() {
+            kept.push_str("\n\n");
+        }
+        kept.push_str(&added_by_hand.join("\n"));
+    }
+    if kept.is_empty() {
+        String::new()
+    } else {
+        format!("{kept}\n")
+    }
+}
+
+/// Puts the footer rendered from `footer` at the end of `content`, replacing
+/// the one rendered from it before instead of adding another one. Merge into
+/// the content [`without_footer`], so the footer stays the last line.
+pub fn with_footer(content: &str, footer: &str, commit: Option<&str>) -> String {
+    let kept = without_footer(content, footer).trim_end();
+    let footer = render_footer(footer, commit);
+    if kept.is_empty() {
+        format!("{footer}\n")
+    } else {
+        format!("{kept}\n\n{footer}\n")
+    }
+}
+
+/// Inserts `lines` at the top of `current`, separated from the rest by a blank
+/// line. A leading block of comments followed by a blank line, like a license
+/// header, stays in front of them. A comment directly followed by patterns is
+/// the heading of a section, which moves down with it.
+fn prepend_lines(current: &str, lines: &str) -> String {
+    let mut current_lines = current.split_inclusive('\n').peekable();
+    let mut header_len = 0;
+    while let Some(line) = current_lines.next_if(|line| line.starts_with('#')) {
+        header_len += line.len();
+    }
+    if current_lines
+        .peek()
+        .is_some_and(|line| !line.trim().is_empty())
+    {
+        header_len = 0;
+    }
+    let (header, rest) = current.split_at(header_len);
+
+    let mut content = String::new();
+    if !header.is_empty() {
+        content.push_str(header);
+        if !header.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push('\n');
+    }
+    content.push_str(lines.trim_matches('\n'));
+    content.push('\n');
+    let rest = rest.trim_start_matches(['\n', '\r']);
+    if !rest.is_empty() {
+        content.push('\n');
+        content.push_str(rest);
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, prepend_lines, trim_pattern, with_footer, without_sections};
+    use crate::cli::MergeStrategy;
+
+    const BANNER: &str = "### Sourced from github/gitignore for: {templates} ###";
+
+    #[test]
+    fn union_appends_only_new_lines() {
+        let current = "/local/\n*.log\n";
+        let new = "# Logs\n*.log\n\ntarget/\n";
+        assert_eq!(
+            merge(current, new, MergeStrategy::Union, None, "", false).as_deref(),
+            Some("/local/\n*.log\n# Logs\n\ntarget/\n")
+        );
+        assert_eq!(
+            merge(current, "*.log\n", MergeStrategy::Union, None, "", false),
+            None
+        );
+    }
+
+    #[test]
+    fn union_keeps_negation_after_new_pattern() {
+        let merged = merge(
+            "!important.log\n",
+            "*.log\n!important.log\n",
+            MergeStrategy::Union,
+            None,
+            "",
+            false,
+        );
+        assert_eq!(
+            merged.as_deref(),
+            Some("!important.log\n*.log\n!important.log\n")
+        );
+    }
+
+    #[test]
+    fn append_keeps_duplicates() {
+        assert_eq!(
+            merge("*.log", "*.log\n", MergeStrategy::Append, None, "", false).as_deref(),
+            Some("*.log\n*.log\n")
+        );
+    }
+
+    #[test]
+    fn replace_overwrites_generated_sections() {
+        let current = "/local/\n\n### Sourced from github/gitignore for: Rust ###\ntarget/\n";
+        let new = "### Sourced from github/gitignore for: Node ###\nnode_modules/\n";
+        assert_eq!(
+            merge(
+                current,
+                new,
+                MergeStrategy::Replace,
+                Some(BANNER),
+                "target/\n",
+                false
+            )
+            .as_deref(),
+            Some("/local/\n\n### Sourced from github/gitignore for: Node ###\nnode_modules/\n")
+        );
+        assert_eq!(
+            merge(
+                current,
+                current,
+                MergeStrategy::Replace,
+                Some(BANNER),
+                "target/\n",
+                false
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn replace_keeps_lines_added_below_generated_sections() {
+        let current = "/local/\n\n### Sourced from github/gitignore for: Rust ###\n# Rust\ntarget/\n*.rs.bk\n\n# Mine\n/scratch/\n";
+        let new = "### Sourced from github/gitignore for: Rust ###\n# Rust\ntarget/\ndebug/\n";
+        // `*.rs.bk` was in the template before, so it's dropped like the rest.
+        assert_eq!(
+            merge(
+                current,
+                new,
+                MergeStrategy::Replace,
+                Some(BANNER),
+                "# Rust\ntarget/\n*.rs.bk\n",
+                false
+            )
+            .as_deref(),
+            Some(
+                "/local/\n\n# Mine\n/scratch/\n\n### Sourced from github/gitignore for: Rust ###\n# Rust\ntarget/\ndebug/\n"
+            )
+        );
+        assert_eq!(
+            without_sections(current, Some(BANNER), "# Rust\ntarget/\n"),
+            "/local/\n\n*.rs.bk\n# Mine\n/scratch/\n"
+        );
+    }
+
+    #[test]
+    fn keeps_escaped_trailing_spaces() {
+        assert_eq!(trim_pattern("foo  \t"), "foo");
+        assert_eq!(trim_pattern("foo\\ "), "foo\\ ");
+        assert_eq!(trim_pattern("foo\\   \r"), "foo\\ ");
+        // The backslash is escaped itself, so the space isn't.
+        assert_eq!(trim_pattern("foo\\\\ "), "foo\\\\");
+    }
+
+    #[test]
+    fn prepends_after_leading_comments() {
+        assert_eq!(prepend_lines("", "*.log"), "*.log\n");
+        assert_eq!(prepend_lines("/local/\n", "*.log"), "*.log\n\n/local/\n");
+        assert_eq!(
+            prepend_lines("# Project rules\n\n/local/\n", "\n*.log\n"),
+            "# Project rules\n\n*.log\n\n/local/\n"
+        );
+        // A comment directly followed by patterns heads a section.
+        assert_eq!(
+            prepend_lines("# Local\n/local/\n", "*.log"),
+            "*.log\n\n# Local\n/local/\n"
+        );
+    }
+
+    #[test]
+    fn replaces_the_footer() {
+        const FOOTER: &str = "# Generated from {commit}";
+        assert_eq!(
+            with_footer("", FOOTER, Some("abc1234")),
+            "# Generated from abc1234\n"
+        );
+        assert_eq!(
+            with_footer(
+                "target/\n\n# Generated from 1234abc\n\n",
+                FOOTER,
+                Some("abc1234")
+            ),
+            "target/\n\n# Generated from abc1234\n"
+        );
+        // Only the last line is a footer, and only if the commit looks like one.
+        assert_eq!(
+            with_footer("# Generated from 1234abc\n*.log\n", FOOTER, Some("abc1234")),
+            "# Generated from 1234abc\n*.log\n\n# Generated from abc1234\n"
+        );
+        assert_eq!(
+            with_footer("*.log\n# my notes\n", "# {commit}", None),
+            "*.log\n# my notes\n\n# unknown\n"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::{
+    error::{Error, Result},
+    ignore,
+};
+
+const CRATES_IO_URL: &str = concat!("https://crates.io/api/v1/crates/", env!("CARGO_PKG_NAME"));
+/// crates.io rejects requests without a user agent.
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Deserialize, Debug)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: Crate,
+}
+
+#[derive(Deserialize, Debug)]
+struct Crate {
+    max_stable_version: String,
+}
+
+/// Asks crates.io for the latest release and prints whether it is newer than
+/// the running version. Nothing is installed.
+pub fn check() -> Result<()> {
+    let network_error = |reason: String| Error::Network {
+        url: CRATES_IO_URL.to_string(),
+        reason,
+    };
+    let res = ignore::http_get(CRATES_IO_URL)
+        .header("User-Agent", USER_AGENT)
+        .send()
+        .map_err(|e| network_error(e.to_string()))?;
+    if !res.is_success() {
+        return Err(network_error(format!("HTTP status: {}", res.status())));
+    }
+    let latest = res
+        .json::<CrateResponse>()
+        .map_err(|e| network_error(e.to_string()))?
+        .krate
+        .max_stable_version;
+
+    let current = env!("CARGO_PKG_VERSION");
+    if is_newer(&latest, current) {
+        println!(
+            "{}: git-ignore {} is available, you have {}. Update with `cargo install {}` or your package manager.",
+            "Info".bold().green(),
+            latest.green(),
+            current,
+            env!("CARGO_PKG_NAME")
+        );
+    } else {
+        println!("git-ignore {current} is up to date");
+    }
+    Ok(())
+}
+
+/// Parses the `major.minor.patch` part of a version, ignoring pre-release and
+/// build metadata.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.split(['-', '+']).next()?;
+    let mut parts = version.split('.').map(|part| part.parse().ok());
+    let version = (parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(version)
+}
+
+/// Whether `latest` is a newer version than `current`, false if either can't
+/// be parsed.
+fn is_newer(latest: &str, current: &str) -> bool {
+    match (parse_version(latest), parse_version(current)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_newer;
+
+    #[test]
+    fn compares_versions() {
+        assert!(is_newer("1.5.0", "1.4.0"));
+        assert!(is_newer("1.10.0", "1.9.3"));
+        assert!(is_newer("2.0.0", "1.99.99"));
+        assert!(!is_newer("1.4.0", "1.4.0"));
+        assert!(!is_newer("1.3.9", "1.4.0"));
+        assert!(!is_newer("1.4.1-beta.1", "1.4.1"));
+        assert!(!is_newer("not a version", "1.4.0"));
+    }
+}
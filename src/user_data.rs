@@ -1,33 +1,127 @@
 use std::{
-    collections::HashMap,
-    fs::{File, read_to_string},
-    io::Write,
+    collections::{BTreeMap, HashMap},
+    fs::read_to_string,
     path::{Path, PathBuf},
-    sync::LazyLock,
+    sync::{LazyLock, OnceLock},
 };
 
-use anyhow::{Context, Result};
 use colored::Colorize;
 use etcetera::AppStrategy;
 use serde::{Deserialize, Serialize};
 
-use crate::ignore::PROJECT_DIRS;
+use crate::{
+    cli::TemplateCase,
+    data::{CACHE_DIR, DEFAULT_BANNER, GIT_REPO_CACHE_DIR},
+    error::{Error, IoContext, Result},
+    ignore::{self, DEFAULT_CACHE_MAX_AGE_DAYS, PROJECT_DIRS},
+};
+
+/// The directive at the start of a user template that names the templates it
+/// builds on, like `#!extends Rust, Node`.
+const EXTENDS_DIRECTIVE: &str = "#!extends ";
+
+/// Splits the `#!extends` directive off a user template, returning the names
+/// it lists and the rest of the content. The directive has to come before the
+/// first pattern, so it can follow the `### name ###` header `template add`
+/// writes. Without it the content is returned as is.
+pub fn parse_extends(content: &str) -> (Vec<String>, String) {
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if let Some(names) = trimmed.strip_prefix(EXTENDS_DIRECTIVE) {
+            let names = names
+                .split([',', ' ', '\t'])
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect();
+            let rest = format!("{}{}", &content[..offset], &content[offset + line.len()..]);
+            return (names, rest);
+        }
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            break;
+        }
+        offset += line.len();
+    }
+    (Vec::new(), content.to_string())
+}
+
+/// The 1-based line of the key `key` in the table `table` of the TOML
+/// `content`, an empty `table` being the top level.
+fn key_line(content: &str, table: &str, key: &str) -> Option<usize> {
+    let mut current_table = "";
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current_table = header.trim();
+        } else if current_table == table
+            && let Some((line_key, _)) = line.split_once('=')
+            && line_key.trim().trim_matches(['"', '\'']) == key
+        {
+            return Some(i + 1);
+        }
+    }
+    None
+}
 
-static CONFIG_FILE: LazyLock<PathBuf> =
-    LazyLock::new(|| PROJECT_DIRS.config_dir().join("config.toml"));
+/// Overrides the config directory for the whole run, set from `--config-dir`
+/// before [`CONFIG_DIR`] is first accessed.
+pub static CONFIG_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+pub static CONFIG_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    CONFIG_DIR_OVERRIDE
+        .get()
+        .cloned()
+        .unwrap_or_else(|| PROJECT_DIRS.config_dir())
+});
+/// Overrides the configured template directory for the whole run, set from
+/// `--template-dir`.
+pub static TEMPLATE_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+/// Overrides the configured template case for the whole run, set from
+/// `--template-case`.
+pub static TEMPLATE_CASE_OVERRIDE: OnceLock<TemplateCase> = OnceLock::new();
+static CONFIG_FILE: LazyLock<PathBuf> = LazyLock::new(|| CONFIG_DIR.join("config.toml"));
 
 #[derive(Deserialize, Serialize, Default, Debug, Clone)]
 pub struct UserData {
     pub aliases: HashMap<String, Vec<String>>,
     pub templates: HashMap<String, String>,
+    #[serde(default)]
+    pub detectors: HashMap<String, String>,
+    /// A directory of additional `.gitignore` templates, keyed by file stem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_dir: Option<PathBuf>,
+    /// Replaces the banner added in front of templates, supports the
+    /// `{templates}` and `{date}` placeholders.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner_template: Option<String>,
+    /// Adds a footer at the end of written `.gitignore` files, supports the
+    /// `{date}` and `{commit}` placeholders.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub footer_template: Option<String>,
+    /// Warn when the template cache hasn't been updated for this many days.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_max_age: Option<u64>,
+    /// Whether template names have to match exactly, see [`UserData::template_case`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_case: Option<TemplateCase>,
+    /// Write to `.gitignore` by default instead of printing, see `--no-write`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_write: Option<bool>,
+    /// An existing checkout of github/gitignore to read templates from, see `--repo-path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo_path: Option<PathBuf>,
+    /// Print the config instead of writing it when it changes, see `--dry-run`.
+    #[serde(skip)]
+    pub dry_run: bool,
 }
 
 impl UserData {
-    pub fn create(force: bool) -> Result<()> {
+    /// Creates the config file, either empty or with the content downloaded
+    /// from `from`. The downloaded content is validated before writing it.
+    pub fn create(force: bool, from: Option<&str>) -> Result<()> {
         UserData::create_dir(
             CONFIG_FILE
                 .parent()
-                .context("No parent dir for the config_file")?,
+                .expect("The config file is always in the config directory"),
         );
 
         if CONFIG_FILE.exists() && !force {
@@ -39,17 +133,221 @@ impl UserData {
             eprintln!("{}: overwriting existing config file", "WARN".bold().red());
         }
 
-        let config = UserData::default();
-        config.write()
+        match from {
+            Some(url) => {
+                let content = UserData::download(url)?;
+                std::fs::write(CONFIG_FILE.as_path(), content)
+                    .io_context(|| format!("Failed to write {:?}", CONFIG_FILE.as_path()))?;
+                eprintln!(
+                    "{}: created config from {}",
+                    "INFO".bold().blue(),
+                    url.yellow()
+                );
+                Ok(())
+            }
+            None => {
+                let config = UserData::default();
+                config.write()
+            }
+        }
+    }
+
+    /// Prints the configuration as it is used, after applying the overrides
+    /// from the command line and filling in the defaults, as TOML or JSON.
+    pub fn show(json: bool) -> Result<()> {
+        UserData::new()?.print(json)
+    }
+
+    /// Prints this configuration like [`UserData::show`] does.
+    fn print(&self, json: bool) -> Result<()> {
+        /// Tables come last, as TOML requires.
+        #[derive(Serialize)]
+        struct Effective<'a> {
+            config_file: &'a Path,
+            cache_dir: &'a Path,
+            repo_dir: &'a Path,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            template_dir: Option<PathBuf>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            default_args: Option<String>,
+            default_write: bool,
+            template_case: TemplateCase,
+            cache_max_age: u64,
+            banner_template: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            footer_template: Option<&'a str>,
+            aliases: BTreeMap<&'a str, &'a [String]>,
+            templates: BTreeMap<&'a str, &'a str>,
+            detectors: BTreeMap<&'a str, &'a str>,
+        }
+
+        let effective = Effective {
+            config_file: CONFIG_FILE.as_path(),
+            cache_dir: CACHE_DIR.as_path(),
+            repo_dir: GIT_REPO_CACHE_DIR.as_path(),
+            template_dir: self.template_dir(),
+            default_args: std::env::var("GIT_IGNORE_ARGS").ok(),
+            default_write: self.default_write.unwrap_or_default(),
+            template_case: self.template_case(),
+            cache_max_age: self.cache_max_age.unwrap_or(DEFAULT_CACHE_MAX_AGE_DAYS),
+            banner_template: self.banner_template.as_deref().unwrap_or(DEFAULT_BANNER),
+            footer_template: self.footer_template.as_deref(),
+            aliases: self
+                .aliases
+                .iter()
+                .map(|(name, targets)| (name.as_str(), targets.as_slice()))
+                .collect(),
+            templates: self
+                .templates
+                .iter()
+                .map(|(name, file)| (name.as_str(), file.as_str()))
+                .collect(),
+            detectors: self
+                .detectors
+                .iter()
+                .map(|(pattern, template)| (pattern.as_str(), template.as_str()))
+                .collect(),
+        };
+        let content = if json {
+            serde_json::to_string_pretty(&effective)
+                .expect("The config is always serializable, this is an internal error")
+        } else {
+            toml::to_string_pretty(&effective).map_err(|e| Error::Serialize("the config", e))?
+        };
+        println!("{}", content.trim_end());
+        Ok(())
+    }
+
+    /// Checks the config for problems that would only show up later, like
+    /// user templates whose file is missing or malformed alias targets, and
+    /// reports them with the line they are on. Fails if there are any.
+    pub fn validate() -> Result<()> {
+        if !CONFIG_FILE.exists() {
+            println!(
+                "No config at {}, nothing to validate",
+                CONFIG_FILE.display()
+            );
+            return Ok(());
+        }
+        let content = read_to_string(CONFIG_FILE.as_path())
+            .io_context(|| format!("Failed to read {:?}", CONFIG_FILE.as_path()))?;
+        let user_data =
+            toml::from_str::<UserData>(&content).map_err(|source| Error::InvalidConfig {
+                context: format!("{} is not valid", CONFIG_FILE.display()),
+                source,
+            })?;
+
+        let problems = user_data.problems(&content);
+        for (line, problem) in &problems {
+            match line {
+                Some(line) => eprintln!(
+                    "{}: {}:{}: {}",
+                    "Error".red().bold(),
+                    CONFIG_FILE.display(),
+                    line,
+                    problem
+                ),
+                None => eprintln!(
+                    "{}: {}: {}",
+                    "Error".red().bold(),
+                    CONFIG_FILE.display(),
+                    problem
+                ),
+            }
+        }
+        if !problems.is_empty() {
+            return Err(Error::ConfigProblems(problems.len()));
+        }
+        println!("{} is valid", CONFIG_FILE.display().to_string().cyan());
+        Ok(())
+    }
+
+    /// The problems [`UserData::validate`] reports, with the line of `content`
+    /// they are on if it can be found.
+    fn problems(&self, content: &str) -> Vec<(Option<usize>, String)> {
+        let mut problems = Vec::new();
+
+        let mut templates: Vec<_> = self.templates.iter().collect();
+        templates.sort();
+        for (name, file) in templates {
+            let path = CONFIG_DIR.join("templates").join(file);
+            if let Err(e) = read_to_string(&path) {
+                problems.push((
+                    key_line(content, "templates", name),
+                    format!("template {name}: can't read {}: {e}", path.display()),
+                ));
+            }
+        }
+
+        let mut aliases: Vec<_> = self.aliases.iter().collect();
+        aliases.sort();
+        for (name, targets) in aliases {
+            let line = key_line(content, "aliases", name);
+            if targets.is_empty() {
+                problems.push((line, format!("alias {name} has no targets")));
+            }
+            for target in targets {
+                if target.trim().is_empty() {
+                    problems.push((line, format!("alias {name} has an empty target")));
+                } else if target.trim() != target {
+                    problems.push((
+                        line,
+                        format!("alias {name}: target {target:?} has surrounding whitespace"),
+                    ));
+                } else if target.contains(',') {
+                    problems.push((
+                        line,
+                        format!("alias {name}: target {target:?} contains a comma, list the targets separately"),
+                    ));
+                }
+            }
+        }
+
+        if let Some(dir) = &self.template_dir
+            && !dir.is_dir()
+        {
+            problems.push((
+                key_line(content, "", "template_dir"),
+                format!("template_dir {} is not a directory", dir.display()),
+            ));
+        }
+
+        problems
+    }
+
+    fn download(url: &str) -> Result<String> {
+        let network_error = |reason: String| Error::Network {
+            url: url.to_string(),
+            reason,
+        };
+        let res = ignore::http_get(url)
+            .send()
+            .map_err(|e| network_error(e.to_string()))?;
+        if !res.is_success() {
+            return Err(network_error(format!("HTTP status: {}", res.status())));
+        }
+        let content = res.text().map_err(|e| network_error(e.to_string()))?;
+        toml::from_str::<UserData>(&content).map_err(|source| Error::InvalidConfig {
+            context: format!("config downloaded from {url} is not valid"),
+            source,
+        })?;
+
+        Ok(content)
     }
 
     pub fn new() -> Result<Self> {
         if CONFIG_FILE.exists() {
             match read_to_string(CONFIG_FILE.as_path()) {
                 Ok(content) => {
-                    toml::from_str::<UserData>(&content).context("could not parse config")
+                    toml::from_str::<UserData>(&content).map_err(|source| Error::InvalidConfig {
+                        context: "could not parse config".to_string(),
+                        source,
+                    })
                 }
-                Err(_) => anyhow::bail!("could not read config file"),
+                Err(source) => Err(Error::Io {
+                    context: "could not read config file".to_string(),
+                    source,
+                }),
             }
         } else {
             Ok(UserData::default())
@@ -57,56 +355,180 @@ impl UserData {
     }
 
     pub fn add_alias(&mut self, name: String, aliases: Vec<String>) -> Result<()> {
-        println!("Created alias {} for {:?}", name.blue(), aliases);
+        self.report(format!("Created alias {} for {:?}", name.blue(), aliases));
         self.aliases.insert(name, aliases);
         self.write()
     }
 
+    /// Adds the targets that aren't in the alias `name` yet to it. Fails if the
+    /// alias doesn't exist, unless `create` is set.
+    pub fn append_alias(&mut self, name: String, aliases: Vec<String>, create: bool) -> Result<()> {
+        let Some(targets) = self.aliases.get_mut(&name) else {
+            if create {
+                return self.add_alias(name, aliases);
+            }
+            return Err(Error::AliasNotFound(name));
+        };
+
+        for alias in aliases {
+            if !targets.contains(&alias) {
+                targets.push(alias);
+            }
+        }
+        let message = format!("Updated alias {} to {:?}", name.blue(), targets);
+        self.report(message);
+        self.write()
+    }
+
     pub fn remove_alias(&mut self, name: &str) -> Result<()> {
         if self.aliases.remove(name).is_some() {
-            println!("Removed alias {}", name.blue());
+            self.report(format!("Removed alias {}", name.blue()));
         } else {
-            println!("No alias named {} found", name.blue());
+            self.report(format!("No alias named {} found", name.blue()));
         }
         self.write()
     }
 
-    pub fn add_template(&mut self, name: String) -> Result<()> {
+    /// Creates a user template, seeded with `content` if given.
+    pub fn add_template(&mut self, name: String, content: Option<String>) -> Result<()> {
         let file_name = format!("{}.ignore", name);
-        let file = PROJECT_DIRS.config_dir().join("templates").join(&file_name);
+        let file = CONFIG_DIR.join("templates").join(&file_name);
 
-        println!(
+        self.report(format!(
             "Created template {} at {}",
             name.blue(),
             file.to_str().unwrap_or_default().yellow()
-        );
+        ));
 
-        let mut file = File::create(file)?;
-        file.write_all(format!("\n### {name} ###\n").as_bytes())?;
+        if !self.dry_run {
+            let content = format!("\n### {name} ###\n") + content.as_deref().unwrap_or_default();
+            std::fs::write(&file, content).io_context(|| format!("Failed to write {:?}", file))?;
+        }
 
         self.templates.insert(name, file_name);
         self.write()
     }
 
+    /// Creates a template in the shared template directory, where everyone
+    /// using that directory picks it up, instead of in the config.
+    pub fn add_shared_template(&self, name: &str, content: Option<String>) -> Result<()> {
+        let dir = self.template_dir().ok_or(Error::NoTemplateDir)?;
+        std::fs::create_dir_all(&dir).io_context(|| format!("Failed to create {:?}", dir))?;
+        let file = dir.join(format!("{name}.gitignore"));
+        std::fs::write(&file, content.unwrap_or_default())
+            .io_context(|| format!("Failed to write {:?}", file))?;
+
+        println!(
+            "Created shared template {} at {}",
+            name.blue(),
+            file.to_str().unwrap_or_default().yellow()
+        );
+        Ok(())
+    }
+
     pub fn remove_template(&mut self, name: &str) -> Result<()> {
         if self.templates.remove(name).is_some() {
-            println!("Removed template {}", name.blue());
+            self.report(format!("Removed template {}", name.blue()));
         } else {
-            println!("No template named {} found", name.blue());
+            self.report(format!("No template named {} found", name.blue()));
         }
         self.write()
     }
 
-    pub fn read_template(path: &str) -> Result<String> {
-        let dir = PROJECT_DIRS.config_dir().join("templates").join(path);
-        let content = read_to_string(dir)?;
+    /// The directory of additional templates, `--template-dir` takes precedence
+    /// over the configured one.
+    pub fn template_dir(&self) -> Option<PathBuf> {
+        TEMPLATE_DIR_OVERRIDE
+            .get()
+            .cloned()
+            .or_else(|| self.template_dir.clone())
+    }
 
-        Ok(content)
+    /// How template names are matched, `--template-case` takes precedence over
+    /// the configured one.
+    pub fn template_case(&self) -> TemplateCase {
+        TEMPLATE_CASE_OVERRIDE
+            .get()
+            .copied()
+            .or(self.template_case)
+            .unwrap_or_default()
     }
 
+    pub fn list_detector_rules(&self) {
+        if self.detectors.is_empty() {
+            return println!("{}", "No detection rules defined".blue());
+        }
+
+        let mut rules = self.detectors.iter().collect::<Vec<_>>();
+        rules.sort_unstable();
+
+        println!("{}", "Available detection rules:".bold().green());
+        for (pattern, template) in rules {
+            println!("{} => {}", pattern.yellow().bold(), template);
+        }
+    }
+
+    pub fn add_detector_rule(&mut self, pattern: String, template: String) -> Result<()> {
+        println!(
+            "Created detection rule {} => {}",
+            pattern.blue(),
+            template.blue()
+        );
+        self.detectors.insert(pattern, template);
+        self.write()
+    }
+
+    pub fn remove_detector_rule(&mut self, pattern: &str) -> Result<()> {
+        if self.detectors.remove(pattern).is_some() {
+            println!("Removed detection rule {}", pattern.blue());
+        } else {
+            println!("No detection rule for {} found", pattern.blue());
+        }
+        self.write()
+    }
+
+    /// The file a user template is read from, either from the config or the
+    /// template directory.
+    pub fn template_path(&self, name: &str) -> Option<PathBuf> {
+        match self.templates.get(name) {
+            Some(file) => Some(CONFIG_DIR.join("templates").join(file)),
+            None => self
+                .template_dir()
+                .map(|dir| dir.join(format!("{name}.gitignore"))),
+        }
+    }
+
+    /// Reads a user template, returning the templates its `#!extends`
+    /// directive names, see [`parse_extends`], and the rest of its content.
+    pub fn read_template(path: &str) -> Result<(Vec<String>, String)> {
+        let dir = CONFIG_DIR.join("templates").join(path);
+        let content = read_to_string(&dir).io_context(|| format!("Failed to read {:?}", dir))?;
+
+        Ok(parse_extends(&content))
+    }
+
+    /// Prints what a change did, unless it's a dry run, where the config is
+    /// printed instead by [`UserData::write`].
+    fn report(&self, message: String) {
+        if !self.dry_run {
+            println!("{message}");
+        }
+    }
+
+    /// Writes the config, or prints it with `dry_run`.
     fn write(&self) -> Result<()> {
-        let mut file = File::create(CONFIG_FILE.as_path())?;
-        file.write_all(toml::to_string_pretty(self)?.as_bytes())?;
+        if self.dry_run {
+            eprintln!(
+                "{}: Dry run, {} was not changed. It would be:",
+                "Info".bold().green(),
+                CONFIG_FILE.display()
+            );
+            return self.print(false);
+        }
+        let content =
+            toml::to_string_pretty(self).map_err(|e| Error::Serialize("the config", e))?;
+        std::fs::write(CONFIG_FILE.as_path(), content)
+            .io_context(|| format!("Failed to write {:?}", CONFIG_FILE.as_path()))?;
 
         Ok(())
     }
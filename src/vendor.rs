@@ -0,0 +1,87 @@
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::Path,
+};
+
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::IgnoreData,
+    error::{Error, IoContext, Result},
+};
+
+/// The name of the manifest written next to the vendored templates.
+pub const MANIFEST_FILE_NAME: &str = "manifest.toml";
+
+/// Records what was vendored, so the templates can be traced back to the
+/// github/gitignore commit they were copied from.
+#[derive(Deserialize, Serialize, Debug)]
+struct Manifest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    commit: Option<String>,
+    templates: Vec<String>,
+}
+
+/// Copies the templates `names` resolve to into `dir`, keeping the layout of
+/// the github/gitignore repository so `--from-vendored` can read them back.
+/// User templates aren't part of the cache and are skipped.
+pub fn vendor_templates(
+    data: &IgnoreData,
+    names: &[String],
+    dir: &Path,
+    commit: Option<String>,
+) -> Result<()> {
+    let keys = data.template_keys(names);
+    for key in &keys {
+        let content = data
+            .get_template(key)?
+            .ok_or_else(|| Error::TemplateNotFound(key.clone()))?;
+        let path = dir.join(format!("{key}.gitignore"));
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .io_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        write(&path, content).io_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    let manifest = Manifest {
+        commit,
+        templates: keys,
+    };
+    create_dir_all(dir).io_context(|| format!("Failed to create {}", dir.display()))?;
+    let manifest_path = dir.join(MANIFEST_FILE_NAME);
+    let content =
+        toml::to_string_pretty(&manifest).map_err(|e| Error::Serialize("the manifest", e))?;
+    write(&manifest_path, content)
+        .io_context(|| format!("Failed to write {}", manifest_path.display()))?;
+
+    eprintln!(
+        "{}: Vendored {} template(s) into {}",
+        "Info".bold().green(),
+        manifest.templates.len(),
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Fails unless every template `names` resolve to is listed in the manifest in
+/// `dir`, so `--from-vendored` doesn't pick up files that were never vendored.
+pub fn check_vendored(data: &IgnoreData, names: &[String], dir: &Path) -> Result<()> {
+    let path = dir.join(MANIFEST_FILE_NAME);
+    let content =
+        read_to_string(&path).io_context(|| format!("Failed to read {}", path.display()))?;
+    let manifest: Manifest =
+        toml::from_str(&content).map_err(|e| Error::InvalidManifest(path.clone(), e))?;
+    match data
+        .template_keys(names)
+        .into_iter()
+        .find(|key| !manifest.templates.contains(key))
+    {
+        Some(key) => Err(Error::NotVendored {
+            key,
+            manifest: path,
+        }),
+        None => Ok(()),
+    }
+}
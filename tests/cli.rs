@@ -0,0 +1,2147 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
+
+use tempfile::TempDir;
+
+const GITHUB_BASE_URL: &str = "https://raw.githubusercontent.com/github/gitignore/main/";
+
+fn fixture_cache() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cache")
+}
+
+struct Sandbox {
+    dir: TempDir,
+    env: Vec<(String, String)>,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("config/templates")).unwrap();
+        fs::create_dir_all(dir.path().join("project")).unwrap();
+        Sandbox {
+            dir,
+            env: Vec::new(),
+        }
+    }
+
+    fn with_env(mut self, key: &str, value: &str) -> Self {
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    fn with_config(self, config: &str) -> Self {
+        fs::write(self.dir.path().join("config/config.toml"), config).unwrap();
+        self
+    }
+
+    /// Runs a subcommand against the fixture cache.
+    fn run_cmd(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+            .current_dir(self.project())
+            .envs(self.env.iter().cloned())
+            .args(args)
+            .arg("--cache-dir")
+            .arg(fixture_cache())
+            .arg("--config-dir")
+            .arg(self.dir.path().join("config"))
+            .output()
+            .unwrap()
+    }
+
+    /// Puts `body` in the response cache of direct fetches as the response for
+    /// `url`, returning the path of the cache file.
+    fn cache_response(&self, url: &str, body: &str) -> PathBuf {
+        let http_cache = self.dir.path().join("cache/http");
+        fs::create_dir_all(&http_cache).unwrap();
+        // The file is named by the 64-bit FNV-1a hash of the URL.
+        let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        let path = http_cache.join(format!("{hash:016x}"));
+        fs::write(&path, body).unwrap();
+        path
+    }
+
+    fn project(&self) -> PathBuf {
+        self.dir.path().join("project")
+    }
+
+    /// Runs the binary against the fixture cache. `--auto` is passed so the
+    /// templates are resolved from the local cache instead of fetched directly,
+    /// the project directory is empty so nothing is autodetected.
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+            .current_dir(self.project())
+            .envs(self.env.iter().cloned())
+            .arg("--cache-dir")
+            .arg(fixture_cache())
+            .arg("--config-dir")
+            .arg(self.dir.path().join("config"))
+            .arg("--auto")
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// Runs the binary without `--auto`, so templates are fetched directly. The
+    /// responses for the fixture templates are seeded in the response cache, so
+    /// nothing is fetched from the network.
+    fn run_direct(&self, args: &[&str]) -> Output {
+        for name in ["Rust", "Node"] {
+            let body = fs::read_to_string(
+                fixture_cache().join(format!("github_gitignore_repo/{name}.gitignore")),
+            )
+            .unwrap();
+            self.cache_response(&format!("{GITHUB_BASE_URL}{name}.gitignore"), &body);
+        }
+
+        Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+            .current_dir(self.project())
+            .envs(self.env.iter().cloned())
+            .arg("--cache-dir")
+            .arg(self.dir.path().join("cache"))
+            .arg("--config-dir")
+            .arg(self.dir.path().join("config"))
+            .args(args)
+            .output()
+            .unwrap()
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn detect_dry_run_prints_trigger_files() {
+    let sandbox = Sandbox::new();
+    fs::write(sandbox.project().join("Cargo.toml"), "").unwrap();
+    let output = sandbox.run_cmd(&["detect", "--dry-run"]);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "TEMPLATE  TRIGGERED BY\nrust      Cargo.toml\n"
+    );
+}
+
+#[test]
+fn prints_single_template() {
+    let output = Sandbox::new().run(&["Rust"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("### Sourced from github/gitignore for: Rust ###"));
+    assert!(stdout.contains("target/"));
+    assert!(!stdout.contains("node_modules/"));
+}
+
+#[cfg(unix)]
+#[test]
+fn follows_symlinked_gitignore_unless_disabled() {
+    let sandbox = Sandbox::new();
+    let target = sandbox.dir.path().join("shared.gitignore");
+    fs::write(&target, "").unwrap();
+    std::os::unix::fs::symlink(&target, sandbox.project().join(".gitignore")).unwrap();
+
+    let output = sandbox.run(&["--write", "--no-follow-symlinks", "Rust"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("is a symlink"));
+    assert_eq!(fs::read_to_string(&target).unwrap(), "");
+
+    let output = sandbox.run(&["--write", "--force", "Rust"]);
+    assert!(output.status.success());
+    assert!(fs::read_to_string(&target).unwrap().contains("target/"));
+}
+
+#[test]
+fn writes_without_locking_when_asked_to() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run(&["--write", "--no-lock", "Rust"]);
+    assert!(output.status.success());
+    let output = sandbox.run_direct(&["--write", "--no-lock", "Node"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(sandbox.project().join(".gitignore")).unwrap();
+    assert!(content.contains("target/"));
+    assert!(content.contains("node_modules/"));
+}
+
+#[test]
+fn falls_back_to_the_local_cache_when_fetching_fails() {
+    let sandbox = Sandbox::new();
+    let repo = sandbox.dir.path().join("cache/github_gitignore_repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("Offline.gitignore"), "/offline/\n").unwrap();
+
+    // Nothing can be fetched through this proxy, Rust comes from the response cache.
+    let args = [
+        "--verbose",
+        "--proxy",
+        "http://127.0.0.1:9",
+        "Offline",
+        "Rust",
+    ];
+    let output = sandbox.run_direct(&args);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("/offline/"));
+    assert!(stdout.contains("target/"));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("using the local cache instead"));
+    assert!(!stderr.contains("Failed to fetch template"));
+}
+
+#[test]
+fn warns_about_empty_templates() {
+    let output = Sandbox::new().run(&["Empty"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).is_empty());
+    let stderr = stderr(&output);
+    assert!(stderr.contains("Template Empty exists but is empty"));
+    assert!(!stderr.contains("Failed to fetch or process"));
+    assert!(!stderr.contains("No templates found"));
+}
+
+#[test]
+fn prints_global_template() {
+    let output = Sandbox::new().run(&["Global/macOS"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains(".DS_Store"));
+}
+
+#[test]
+fn resolves_built_in_synonyms_after_user_aliases() {
+    let output = Sandbox::new().run(&["rs"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("target/"));
+
+    let sandbox = Sandbox::new().with_config(
+        r#"
+[aliases]
+rs = ["Node"]
+
+[templates]
+"#,
+    );
+    let output = sandbox.run(&["rs"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("node_modules/"));
+    assert!(!stdout.contains("target/"));
+}
+
+#[test]
+fn resolves_nested_community_templates_ignoring_case() {
+    let output = Sandbox::new().run(&["community/golang/hugo"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("/public/"));
+}
+
+#[test]
+fn matches_exact_case_when_asked_to() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run(&["rust", "--template-case", "exact"]);
+    assert!(stderr(&output).contains("No template named rust, did you mean Rust?"));
+    assert!(!self::stdout(&output).contains("target/"));
+
+    let output = sandbox.run(&["Rust", "--template-case", "exact"]);
+    assert!(self::stdout(&output).contains("target/"));
+
+    // Names that only differ in case aren't duplicates then.
+    let output = sandbox.run(&["--template-case", "exact", "rust", "Rust"]);
+    assert!(self::stdout(&output).contains("target/"));
+
+    let sandbox =
+        Sandbox::new().with_config("template_case = \"exact\"\n[aliases]\n\n[templates]\n");
+    let output = sandbox.run(&["rust"]);
+    assert!(stderr(&output).contains("did you mean Rust?"));
+    let output = sandbox.run(&["rust", "--template-case", "insensitive"]);
+    assert!(self::stdout(&output).contains("target/"));
+}
+
+#[test]
+fn separates_templates_by_a_blank_line() {
+    let stdout = stdout(&Sandbox::new().run(&["Rust", "Node"]));
+    assert!(stdout.contains("**/*.rs.bk\n\n# Logs\n"));
+    assert!(stdout.ends_with("node_modules/\n"));
+}
+
+#[test]
+fn trims_trailing_whitespace_from_written_lines() {
+    let sandbox = Sandbox::new();
+    let content = "/local/   \n*.tmp\t\n";
+    let output = sandbox.run_cmd(&["template", "add", "local", "--content", content]);
+    assert!(output.status.success());
+
+    let output = sandbox.run(&["local"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("/local/\n*.tmp\n"));
+}
+
+#[test]
+fn expands_template_globs() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run(&["global/*", "Node", "community/*"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("for: Node, community/Golang/Hugo, Global/macOS ###"));
+    assert!(stdout.contains(".DS_Store"));
+
+    let output = sandbox.run_cmd(&["Nope*"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("No templates match Nope*"));
+    assert_eq!(self::stdout(&output), "");
+}
+
+#[test]
+fn leaves_out_global_templates() {
+    let sandbox = Sandbox::new()
+        .with_config("[aliases]\nstack = [\"Rust\", \"Global/macOS\"]\n\n[templates]\n");
+    let output = sandbox.run(&["*", "--no-global"]);
+    assert!(output.status.success());
+    let stdout = self::stdout(&output);
+    assert!(stdout.contains("target/"));
+    assert!(!stdout.contains("Global/macOS"));
+    assert!(!stdout.contains(".DS_Store"));
+
+    let output = sandbox.run(&["stack", "Global/macOS", "--no-global"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("Skipping Global/macOS because of --no-global"));
+    let stdout = self::stdout(&output);
+    assert!(stdout.contains("for: stack ###"));
+    assert!(stdout.contains("target/"));
+    assert!(!stdout.contains(".DS_Store"));
+}
+
+#[test]
+fn looks_templates_up_in_a_namespace() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run(&["--namespace", "community", "golang/hugo", "Rust"]);
+    assert!(output.status.success());
+    let stdout = self::stdout(&output);
+    assert!(stdout.contains("for: community/golang/hugo, Rust ###"));
+    assert!(stdout.contains("/public/"));
+    assert!(stdout.contains("target/"));
+}
+
+#[test]
+fn looks_directly_fetched_templates_up_in_a_namespace() {
+    let sandbox = Sandbox::new();
+    let repo = sandbox.dir.path().join("cache/github_gitignore_repo");
+    fs::create_dir_all(repo.join("community/Golang")).unwrap();
+    fs::write(repo.join("Rust.gitignore"), "target/\n").unwrap();
+    fs::write(repo.join("community/Golang/Hugo.gitignore"), "/public/\n").unwrap();
+    sandbox.cache_response(
+        &format!("{GITHUB_BASE_URL}community/Golang/Hugo.gitignore"),
+        "/fetched/\n",
+    );
+
+    let output = sandbox.run_direct(&[
+        "--proxy",
+        "http://127.0.0.1:9",
+        "--verbose",
+        "--namespace",
+        "community",
+        "golang/hugo",
+        "Rust",
+    ]);
+    assert!(output.status.success());
+    let stdout = self::stdout(&output);
+    assert!(stdout.contains("/fetched/\n"));
+    assert!(stdout.contains("debug/\n"));
+    // Rust isn't in the namespace of the local cache, so it isn't requested there.
+    assert!(!stderr(&output).contains("community/Rust.gitignore"));
+}
+
+#[test]
+fn prints_the_cache_commit() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run_cmd(&["--print-cache-commit"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("is not a git repository, its commit is unknown"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .current_dir(sandbox.project())
+        .arg("--print-cache-commit")
+        .arg("--cache-dir")
+        .arg(sandbox.dir.path().join("missing"))
+        .arg("--config-dir")
+        .arg(sandbox.dir.path().join("config"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("No github/gitignore cache found"));
+}
+
+#[test]
+fn checks_for_updates_without_pulling() {
+    let sandbox = Sandbox::new();
+    let git = |dir: &Path, args: &[&str]| {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+    };
+    let upstream = sandbox.dir.path().join("upstream");
+    let cache = sandbox.dir.path().join("cache");
+    let repo = cache.join("github_gitignore_repo");
+    fs::create_dir_all(&upstream).unwrap();
+    fs::write(upstream.join("Rust.gitignore"), "target/\n").unwrap();
+    git(&upstream, &["init", "--quiet"]);
+    git(&upstream, &["add", "."]);
+    git(&upstream, &["commit", "--quiet", "-m", "Add Rust"]);
+    git(
+        sandbox.dir.path(),
+        &[
+            "clone",
+            "--quiet",
+            upstream.to_str().unwrap(),
+            repo.to_str().unwrap(),
+        ],
+    );
+
+    let check = || {
+        Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+            .current_dir(sandbox.project())
+            .arg("--check-updates")
+            .arg("--cache-dir")
+            .arg(&cache)
+            .arg("--config-dir")
+            .arg(sandbox.dir.path().join("config"))
+            .output()
+            .unwrap()
+    };
+    let output = check();
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("cache is up to date"));
+
+    fs::write(upstream.join("Node.gitignore"), "node_modules/\n").unwrap();
+    git(&upstream, &["add", "."]);
+    git(&upstream, &["commit", "--quiet", "-m", "Add Node"]);
+    let output = check();
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("cache is 1 commit(s) behind"));
+    assert!(!repo.join("Node.gitignore").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn removes_the_clone_when_it_times_out_or_fails() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let sandbox = Sandbox::new();
+    let bin = sandbox.dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    // Leaves a partial clone in the last argument, the target directory.
+    let fake_git = |rest: &str| {
+        let script = bin.join("git");
+        fs::write(
+            &script,
+            format!("#!/bin/sh\nfor dir; do :; done\nmkdir -p \"$dir\"\n{rest}\n"),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    };
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    let cache = sandbox.dir.path().join("cache");
+    let update = || {
+        Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+            .current_dir(sandbox.project())
+            .env("PATH", &path)
+            .args(["-u", "--quiet", "--git-timeout", "1"])
+            .arg("--cache-dir")
+            .arg(&cache)
+            .arg("--config-dir")
+            .arg(sandbox.dir.path().join("config"))
+            .output()
+            .unwrap()
+    };
+
+    fake_git("exec sleep 10");
+    let output = update();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("'git clone' timed out after 1s"));
+    assert!(!cache.join("github_gitignore_repo").exists());
+
+    fake_git("exit 128");
+    let output = update();
+    assert!(stderr(&output).contains("Failed to clone gitignore repository"));
+    assert!(!cache.join("github_gitignore_repo").exists());
+}
+
+#[test]
+fn fails_without_waiting_for_a_cache_being_created() {
+    let sandbox = Sandbox::new();
+    let cache = sandbox.dir.path().join("cache");
+    fs::create_dir_all(&cache).unwrap();
+    let lock = fs::File::create(cache.join("update.lock")).unwrap();
+    lock.lock().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .current_dir(sandbox.project())
+        .args(["--auto", "--no-wait", "Rust"])
+        .arg("--cache-dir")
+        .arg(&cache)
+        .arg("--config-dir")
+        .arg(sandbox.dir.path().join("config"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Another process is still creating the cache"));
+}
+
+#[test]
+fn writes_by_default_when_configured() {
+    let sandbox = Sandbox::new().with_config("default_write = true\n\n[aliases]\n\n[templates]\n");
+    let gitignore = sandbox.project().join(".gitignore");
+
+    let output = sandbox.run(&["Node", "--no-write"]);
+    assert!(stdout(&output).contains("node_modules/"));
+    assert!(!gitignore.exists());
+
+    let output = sandbox.run(&["Rust", "--output-format", "markdown"]);
+    assert!(stdout(&output).starts_with("```gitignore\n"));
+    let output = sandbox.run(&["--list"]);
+    assert!(stdout(&output).contains("Rust"));
+    assert!(!gitignore.exists());
+
+    let output = sandbox.run(&["Rust"]);
+    assert!(output.status.success());
+    assert!(fs::read_to_string(&gitignore).unwrap().contains("target/"));
+}
+
+#[test]
+fn shows_the_added_lines_when_asked_to() {
+    let sandbox = Sandbox::new();
+    fs::write(sandbox.project().join(".gitignore"), "*.log\n").unwrap();
+    let output = sandbox.run(&["Node", "--write", "--show-added"]);
+    assert!(output.status.success());
+    let stderr = stderr(&output);
+    assert!(stderr.contains("Added from Node:\n"));
+    assert!(stderr.contains("  + node_modules/\n"));
+    assert!(!stderr.contains("+ *.log"));
+
+    let output = sandbox.run_direct(&["Node", "Rust", "--write", "--show-added"]);
+    assert!(output.status.success());
+    let stderr = self::stderr(&output);
+    assert!(!stderr.contains("Added from Node"));
+    assert!(stderr.contains("Added from Rust:\n"));
+    assert!(stderr.contains("  + target/\n"));
+}
+
+#[test]
+fn reads_templates_from_an_existing_checkout() {
+    let sandbox = Sandbox::new();
+    let checkout = fixture_cache().join("github_gitignore_repo");
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+            .current_dir(sandbox.project())
+            .args(args)
+            .arg("--cache-dir")
+            .arg(sandbox.dir.path().join("missing"))
+            .arg("--config-dir")
+            .arg(sandbox.dir.path().join("config"))
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&["--auto", "--repo-path", checkout.to_str().unwrap(), "Rust"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("target/"));
+    assert!(!stderr(&output).contains("attempting to clone"));
+
+    let output = run(&[
+        "--auto",
+        "--repo-path",
+        sandbox.project().to_str().unwrap(),
+        "Rust",
+    ]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("doesn't look like a checkout of github/gitignore"));
+
+    fs::write(
+        sandbox.dir.path().join("config/config.toml"),
+        format!("repo_path = {checkout:?}\n\n[aliases]\n\n[templates]\n"),
+    )
+    .unwrap();
+    let output = run(&["--list", "--format", "plain", "Node"]);
+    assert_eq!(stdout(&output), "Node\n");
+    let output = run(&["-u"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("update it with git instead"));
+}
+
+#[test]
+fn notes_when_a_name_matches_a_root_and_a_global_template() {
+    let sandbox = Sandbox::new();
+    let checkout = sandbox.dir.path().join("checkout");
+    fs::create_dir_all(checkout.join("Global")).unwrap();
+    fs::write(checkout.join("Foo.gitignore"), "/root-foo/\n").unwrap();
+    fs::write(checkout.join("Global/Foo.gitignore"), "/global-foo/\n").unwrap();
+    let checkout = checkout.to_str().unwrap();
+
+    let output = sandbox.run(&["--repo-path", checkout, "foo"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("/root-foo/"));
+    assert!(!stdout.contains("/global-foo/"));
+    assert!(stderr(&output).contains("Both Foo and Global/Foo exist, using Foo"));
+
+    let output = sandbox.run(&["--repo-path", checkout, "Global/Foo"]);
+    assert!(self::stdout(&output).contains("/global-foo/"));
+    assert!(!stderr(&output).contains("Both Foo and Global/Foo exist"));
+}
+
+#[test]
+fn summarizes_the_cache_when_verbose() {
+    let sandbox = Sandbox::new();
+    sandbox.run_cmd(&["template", "add", "docs", "--content", "/site/\n"]);
+    let output = sandbox.run(&["--verbose", "Rust"]);
+    assert!(output.status.success());
+    let summary = format!(
+        "VERBOSE: Cache at {} (exists), loaded 5 template(s), 0 alias(es) and 1 user template(s)\n",
+        fixture_cache().join("github_gitignore_repo").display()
+    );
+    assert!(stderr(&output).contains(&summary));
+}
+
+#[test]
+fn fails_on_empty_results_when_asked_to() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run(&["Empty"]);
+    assert!(output.status.success());
+    let output = sandbox.run(&["Empty", "--fail-on-empty"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("The templates added no new lines"));
+
+    let output = sandbox.run(&["Rust", "--write", "--fail-on-empty"]);
+    assert!(output.status.success());
+    let output = sandbox.run(&["Rust", "--write", "--fail-on-empty"]);
+    assert!(!output.status.success());
+
+    let output = sandbox.run_direct(&["Rust", "--write", "--fail-on-empty"]);
+    assert!(!output.status.success());
+    let output = sandbox.run_direct(&["Node", "--write", "--fail-on-empty"]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn writes_to_multiple_output_targets() {
+    let sandbox = Sandbox::new();
+    let repo = sandbox.project().join(".gitignore");
+    let global = sandbox.dir.path().join("excludes");
+    fs::write(&global, "node_modules/\n").unwrap();
+    let (repo_arg, global_arg) = (repo.to_str().unwrap(), global.to_str().unwrap());
+
+    let output = sandbox.run(&[
+        "Node", "--write", "--output", repo_arg, "--output", global_arg,
+    ]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains(&format!("Created {repo_arg} ")));
+    assert!(stdout.contains(&format!(
+        "Appended content from local cache to {global_arg} "
+    )));
+    assert!(fs::read_to_string(&repo).unwrap().contains("node_modules/"));
+    let global_content = fs::read_to_string(&global).unwrap();
+    assert_eq!(global_content.matches("node_modules/").count(), 1);
+    assert!(global_content.contains("logs"));
+
+    let output = sandbox.run_direct(&[
+        "Rust", "--write", "--output", repo_arg, "--output", global_arg,
+    ]);
+    assert!(output.status.success());
+    // The templates are fetched once for all targets.
+    assert_eq!(
+        self::stdout(&output)
+            .matches("Successfully processed template(s)")
+            .count(),
+        1
+    );
+    assert!(fs::read_to_string(&repo).unwrap().contains("target/"));
+    assert!(fs::read_to_string(&global).unwrap().contains("target/"));
+}
+
+#[test]
+fn requires_a_git_repo_when_asked_to() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run(&["Rust", "--write", "--require-git-repo"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("is not inside a git repository"));
+    assert!(!sandbox.project().join(".gitignore").exists());
+
+    let output = sandbox.run(&["Rust", "--write"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("is not inside a git repository, writing .gitignore anyway"));
+
+    fs::create_dir(sandbox.project().join(".git")).unwrap();
+    let output = sandbox.run(&["Node", "--write", "--require-git-repo"]);
+    assert!(output.status.success());
+    assert!(!stderr(&output).contains("is not inside a git repository"));
+}
+
+#[test]
+fn prints_templates_as_a_markdown_snippet() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run(&["Rust", "--output-format", "markdown"]);
+    assert!(output.status.success());
+    let stdout = self::stdout(&output);
+    assert!(stdout.starts_with("```gitignore\n### Sourced from github/gitignore for: Rust ###\n"));
+    assert!(stdout.ends_with("\n```\n"));
+
+    let output = sandbox.run_direct(&["Node", "--output-format", "markdown"]);
+    assert!(output.status.success());
+    let stdout = self::stdout(&output);
+    assert!(stdout.starts_with("```gitignore\n"));
+    assert!(stdout.contains("node_modules/\n```\n"));
+    assert!(!stdout.contains("Successfully processed"));
+}
+
+#[test]
+fn limits_the_number_of_templates() {
+    let sandbox = Sandbox::new()
+        .with_config("[aliases]\nstack = [\"Rust\", \"Node\", \"Global/macOS\"]\n\n[templates]\n");
+    let output = sandbox.run(&["*", "--limit-templates", "2"]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("exceeding the limit of 2, pass --limit-templates to raise it")
+    );
+
+    let output = sandbox.run(&["stack", "--limit-templates", "2"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Expansion produced 3 templates"));
+
+    let output = sandbox.run(&["stack", "--limit-templates", "3"]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn vendors_templates_and_reads_them_back() {
+    let sandbox = Sandbox::new().with_config("[aliases]\nweb = [\"Node\"]\n\n[templates]\n");
+    let vendor = sandbox.dir.path().join("vendor");
+    let output = sandbox.run(&[
+        "--vendor",
+        vendor.to_str().unwrap(),
+        "Rust",
+        "web",
+        "Global/macOS",
+    ]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("Vendored 3 template(s)"));
+    assert!(vendor.join("Rust.gitignore").exists());
+    assert!(vendor.join("Node.gitignore").exists());
+    assert!(vendor.join("Global/macOS.gitignore").exists());
+    assert_eq!(
+        fs::read_to_string(vendor.join("manifest.toml")).unwrap(),
+        "templates = [\n    \"Rust\",\n    \"Node\",\n    \"Global/macOS\",\n]\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .current_dir(sandbox.project())
+        .arg("--cache-dir")
+        .arg(sandbox.dir.path().join("empty-cache"))
+        .arg("--config-dir")
+        .arg(sandbox.dir.path().join("config"))
+        .arg("--from-vendored")
+        .arg(&vendor)
+        .arg("Rust")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("target/"));
+    assert!(!sandbox.dir.path().join("empty-cache").exists());
+
+    fs::write(vendor.join("Go.gitignore"), "*.exe\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .current_dir(sandbox.project())
+        .arg("--cache-dir")
+        .arg(sandbox.dir.path().join("empty-cache"))
+        .arg("--config-dir")
+        .arg(sandbox.dir.path().join("config"))
+        .arg("--from-vendored")
+        .arg(&vendor)
+        .arg("Go")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Go isn't listed in"));
+}
+
+#[test]
+fn appends_to_existing_alias() {
+    let sandbox = Sandbox::new().with_config("[aliases]\nnode = [\"Node\"]\n\n[templates]\n");
+    let output = sandbox.run_cmd(&["alias", "add", "node", "Deno", "Node", "--append"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Updated alias node to [\"Node\", \"Deno\"]"));
+
+    let output = sandbox.run_cmd(&["alias", "add", "web", "Node", "--append"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No alias named web found, pass --create to create it"));
+
+    let output = sandbox.run_cmd(&["alias", "add", "web", "Node", "--append", "--create"]);
+    assert!(output.status.success());
+    let config = fs::read_to_string(sandbox.dir.path().join("config/config.toml")).unwrap();
+    assert!(config.contains("web = [\"Node\"]"));
+    assert!(config.contains("node = [\n    \"Node\",\n    \"Deno\",\n]"));
+}
+
+#[test]
+fn lists_aliases_as_json() {
+    let sandbox =
+        Sandbox::new().with_config("[aliases]\nnode = [\"node\", \"deno\"]\n\n[templates]\n");
+    let output = sandbox.run_cmd(&["alias", "list", "--json"]);
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([{ "name": "node", "targets": ["node", "deno"] }])
+    );
+}
+
+#[test]
+fn reads_alias_targets_from_stdin() {
+    let sandbox = Sandbox::new().with_config("[aliases]\n\n[templates]\n");
+    let add_from_stdin = |input: &[u8]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+            .args(["alias", "add", "stack", "-", "--config-dir"])
+            .arg(sandbox.dir.path().join("config"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let output = add_from_stdin(b"Rust Node\n\nGlobal/macOS\n");
+    assert!(output.status.success());
+    let output = sandbox.run_cmd(&["alias", "list", "--json"]);
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([{ "name": "stack", "targets": ["Rust", "Node", "Global/macOS"] }])
+    );
+
+    let output = add_from_stdin(b"\n  \n");
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No alias targets were given on stdin"));
+}
+
+/// Serves `body` to the first request on a local port, returning its URL.
+fn serve_once(body: &'static str) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/config.toml", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 4096];
+        let _ = std::io::Read::read(&mut stream, &mut request);
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+    });
+    url
+}
+
+#[test]
+fn initializes_the_config_from_a_url() {
+    let sandbox = Sandbox::new().with_env("NO_PROXY", "*");
+    let config = sandbox.dir.path().join("config/config.toml");
+    let shared = "[aliases]\nweb = [\"Node\"]\n\n[templates]\n";
+    let url = serve_once(shared);
+    let output = sandbox.run_cmd(&["init", "--from", &url]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("created config from"));
+    assert_eq!(fs::read_to_string(&config).unwrap(), shared);
+
+    let url = serve_once("aliases = 1\n");
+    let output = sandbox.run_cmd(&["init", "--force", "--from", &url]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("is not valid"));
+    assert_eq!(fs::read_to_string(&config).unwrap(), shared);
+}
+
+#[test]
+fn shows_the_effective_config() {
+    let sandbox = Sandbox::new()
+        .with_config("cache_max_age = 7\n\n[aliases]\nweb = [\"Node\"]\n\n[templates]\n");
+    let output = sandbox.run_cmd(&["config", "show", "--template-dir", "shared"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("template_dir = \"shared\"\n"));
+    assert!(stdout.contains("template_case = \"insensitive\"\n"));
+    assert!(stdout.contains("cache_max_age = 7\n"));
+    assert!(stdout.contains(
+        "banner_template = \"### Sourced from github/gitignore for: {templates} ###\"\n"
+    ));
+    assert!(stdout.contains("[aliases]\nweb = [\"Node\"]\n"));
+
+    let output = sandbox.run_cmd(&["config", "show", "--json"]);
+    let json: serde_json::Value = serde_json::from_str(&self::stdout(&output)).unwrap();
+    assert_eq!(json["default_write"], false);
+    assert_eq!(json["aliases"]["web"][0], "Node");
+}
+
+#[test]
+fn previews_config_changes_with_dry_run() {
+    let config = "[aliases]\nweb = [\"Node\"]\n\n[templates]\n";
+    let sandbox = Sandbox::new().with_config(config);
+    let config_file = sandbox.dir.path().join("config/config.toml");
+
+    let output = sandbox.run_cmd(&["alias", "add", "stack", "Rust", "Go", "--dry-run"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(!stdout.contains("Created alias"));
+    assert!(stdout.contains("stack = [\n    \"Rust\",\n    \"Go\",\n]\n"));
+    assert!(stdout.contains("web = [\"Node\"]"));
+    assert!(stderr(&output).contains("Dry run"));
+
+    let output = sandbox.run_cmd(&["alias", "remove", "web", "--dry-run"]);
+    assert!(output.status.success());
+    assert!(!self::stdout(&output).contains("web"));
+
+    let output = sandbox.run_cmd(&["template", "add", "docs", "--dry-run"]);
+    assert!(output.status.success());
+    assert!(self::stdout(&output).contains("docs = \"docs.ignore\""));
+    assert!(
+        !sandbox
+            .dir
+            .path()
+            .join("config/templates/docs.ignore")
+            .exists()
+    );
+
+    assert_eq!(fs::read_to_string(config_file).unwrap(), config);
+}
+
+#[test]
+fn validates_the_config() {
+    let sandbox = Sandbox::new().with_config(
+        r#"
+[aliases]
+web = ["Node", "Rust, Go"]
+empty = []
+
+[templates]
+docs = "docs.ignore"
+missing = "missing.ignore"
+"#,
+    );
+    fs::write(
+        sandbox.dir.path().join("config/templates/docs.ignore"),
+        "/site/\n",
+    )
+    .unwrap();
+    let output = sandbox.run_cmd(&["config", "validate"]);
+    assert!(!output.status.success());
+    let stderr = self::stderr(&output);
+    assert!(stderr.contains("config.toml:8: template missing: can't read"));
+    assert!(stderr.contains("config.toml:3: alias web: target \"Rust, Go\" contains a comma"));
+    assert!(stderr.contains("config.toml:4: alias empty has no targets"));
+    assert!(stderr.contains("Found 3 problem(s) in the config"));
+    assert!(!stderr.contains("docs"));
+
+    let sandbox = Sandbox::new().with_config("[aliases]\nweb = [\"Node\"]\n\n[templates]\n");
+    let output = sandbox.run_cmd(&["config", "validate"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("is valid"));
+
+    let sandbox = Sandbox::new().with_config("[aliases]\nweb = \"Node\"\n");
+    let output = sandbox.run_cmd(&["config", "validate"]);
+    assert!(!output.status.success());
+    assert!(self::stderr(&output).contains("line 2"));
+}
+
+#[test]
+fn preserves_blank_lines_when_asked_to() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run_direct(&["Node"]);
+    assert!(stdout(&output).contains("*.log\n# Dependency directories\n"));
+
+    let output = sandbox.run_direct(&["Node", "--preserve-blanks"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("*.log\n\n# Dependency directories\n"));
+}
+
+#[test]
+fn counts_listed_templates() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run_cmd(&["--list", "--count-only", "Rust", "Node"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "2\n");
+
+    let output = sandbox.run_cmd(&["--list", "--count-only", "nothing-matches"]);
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "0\n");
+}
+
+#[test]
+fn reads_gitignore_with_bom_or_invalid_utf8() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+    fs::write(&gitignore, "\u{feff}node_modules/\n").unwrap();
+    let output = sandbox.run_direct(&["Node", "--write"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert_eq!(content.matches("node_modules/").count(), 1);
+
+    fs::write(&gitignore, b"logs\n\xff\xfe\n").unwrap();
+    let output = sandbox.run_direct(&["Node", "--write"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("is not valid UTF-8, invalid bytes are replaced"));
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert_eq!(content.lines().filter(|line| *line == "logs").count(), 1);
+    assert!(content.contains("*.log"));
+}
+
+#[test]
+fn writes_a_bom_only_when_creating_gitignore() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+    let output = sandbox.run(&["--write", "--output-encoding", "utf8-bom", "Rust"]);
+    assert!(output.status.success());
+    let output = sandbox.run_direct(&["--write", "--output-encoding", "utf8-bom", "Node"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(content.starts_with("\u{feff}"));
+    assert_eq!(content.matches('\u{feff}').count(), 1);
+    assert!(content.contains("node_modules/"));
+
+    fs::remove_file(&gitignore).unwrap();
+    let output = sandbox.run_direct(&["--write", "--output-encoding", "utf8-bom", "Node"]);
+    assert!(output.status.success());
+    assert!(fs::read(&gitignore).unwrap().starts_with(b"\xEF\xBB\xBF"));
+
+    let output = sandbox.run(&["--write", "--force", "Rust"]);
+    assert!(output.status.success());
+    assert!(!fs::read(&gitignore).unwrap().starts_with(b"\xEF\xBB\xBF"));
+}
+
+#[test]
+fn resolves_arguments_to_template_keys() {
+    let sandbox = Sandbox::new().with_config(
+        "[aliases]\nstack = [\"rust\", \"Global/macOS\", \"missing\"]\n\n[templates]\n",
+    );
+    sandbox.run_cmd(&["template", "add", "docs", "--content", "/site/\n"]);
+    let output = sandbox.run_cmd(&[
+        "--resolve-only",
+        "node",
+        "stack",
+        "docs",
+        "Rust",
+        "global/*",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "Node\nRust\nGlobal/macOS\ndocs\n");
+    assert!(stderr(&output).contains("Could not resolve: missing"));
+}
+
+#[test]
+fn tidies_the_existing_gitignore() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run_cmd(&["--tidy"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("no .gitignore"));
+
+    let path = sandbox.project().join(".gitignore");
+    fs::write(
+        &path,
+        "*.log\ntarget/\n\n\n\n# Logs\n*.log\nlogs\ntarget/\n",
+    )
+    .unwrap();
+    let output = sandbox.run_cmd(&["--tidy"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Removed 2 duplicate line(s)"));
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "*.log\ntarget/\n\n# Logs\nlogs\n"
+    );
+
+    let output = sandbox.run_cmd(&["--tidy"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("already tidy"));
+}
+
+#[test]
+fn deduplicates_template_arguments_ignoring_case() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run_direct(&["rust", "Rust", "RUST"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("### Sourced from github/gitignore for: rust ###\n"));
+
+    let output = sandbox.run(&["Node", "rust", "node", "Rust"]);
+    assert!(stdout(&output).contains("for: Node, rust ###\n"));
+}
+
+#[test]
+fn expands_aliases() {
+    let sandbox = Sandbox::new().with_config(
+        r#"
+[aliases]
+stack = ["Rust", "Global/macOS"]
+
+[templates]
+"#,
+    );
+    let output = sandbox.run(&["stack"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("target/"));
+    assert!(stdout.contains(".DS_Store"));
+    assert!(!stdout.contains("node_modules/"));
+}
+
+#[test]
+fn rewrite_does_not_duplicate_content() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+
+    let output = sandbox.run(&["--write", "Rust"]);
+    assert!(output.status.success());
+    let first = fs::read_to_string(&gitignore).unwrap();
+    assert_eq!(first.matches("target/").count(), 1);
+
+    let output = sandbox.run(&["--write", "Rust"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("'.gitignore' already contains the templates"));
+    assert_eq!(fs::read_to_string(&gitignore).unwrap(), first);
+}
+
+#[test]
+fn merges_with_the_given_strategy() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+
+    fs::write(&gitignore, "/local/\ntarget/\n").unwrap();
+    let output = sandbox.run(&["--write", "Rust"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert_eq!(content.matches("target/").count(), 1);
+
+    let output = sandbox.run(&["--write", "Rust", "--merge-strategy", "append"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert_eq!(content.matches("target/").count(), 2);
+
+    let output = sandbox.run(&["--write", "Node", "--merge-strategy", "replace"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(content.starts_with("/local/\ntarget/\n\n### Sourced from github/gitignore for: Node"));
+    assert!(!content.contains("for: Rust"));
+
+    fs::write(&gitignore, "/local/\ntarget/\n").unwrap();
+    let output = sandbox.run_direct(&["--write", "Rust", "--merge-strategy", "append"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert_eq!(content.matches("target/").count(), 2);
+}
+
+#[test]
+fn replace_keeps_lines_added_below_generated_sections() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+
+    assert!(sandbox.run(&["--write", "Rust"]).status.success());
+    let mut content = fs::read_to_string(&gitignore).unwrap();
+    content.push_str("\n# Mine\n/scratch/\n");
+    fs::write(&gitignore, content).unwrap();
+
+    let output = sandbox.run(&["--write", "Node", "--merge-strategy", "replace"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(content.starts_with("# Mine\n/scratch/\n\n"));
+    assert!(!content.contains("for: Rust") && !content.contains("target/"));
+    assert!(content.contains("node_modules/"));
+
+    let output = sandbox.run_direct(&["--write", "Rust", "--merge-strategy", "replace"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(content.starts_with("# Mine\n/scratch/\n"));
+    assert!(!content.contains("node_modules/") && content.contains("target/"));
+}
+
+#[test]
+fn replace_drops_lines_removed_from_the_templates() {
+    let sandbox = Sandbox::new();
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(sandbox.dir.path().join("cache/github_gitignore_repo"))
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+    };
+    let repo = sandbox.dir.path().join("cache/github_gitignore_repo");
+    fs::create_dir_all(repo.join("Global")).unwrap();
+    fs::write(repo.join("Rust.gitignore"), "target/\n*.rs.bk\n").unwrap();
+    git(&["init", "--quiet"]);
+    git(&["add", "."]);
+    git(&["commit", "--quiet", "-m", "Add Rust"]);
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+            .current_dir(sandbox.project())
+            .args(args)
+            .arg("--cache-dir")
+            .arg(sandbox.dir.path().join("cache"))
+            .arg("--config-dir")
+            .arg(sandbox.dir.path().join("config"))
+            .output()
+            .unwrap()
+    };
+    let replace = ["--auto", "--write", "Rust", "--merge-strategy", "replace"];
+    let gitignore = sandbox.project().join(".gitignore");
+    assert!(run(&replace).status.success());
+    let mut content = fs::read_to_string(&gitignore).unwrap();
+    content.push_str("/scratch/\n");
+    fs::write(&gitignore, content).unwrap();
+
+    fs::write(repo.join("Rust.gitignore"), "target/\ndebug/\n").unwrap();
+    git(&["commit", "--quiet", "--all", "-m", "Update Rust"]);
+    let output = run(&["--verify"]);
+    assert!(!output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("\n-*.rs.bk\n+debug/\n"));
+    assert!(!stdout.contains("scratch"));
+
+    let output = run(&replace);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(content.starts_with("/scratch/\n\n"));
+    assert!(content.contains("debug/") && !content.contains("*.rs.bk"));
+}
+
+#[test]
+fn writes_a_gitignore_per_subdirectory() {
+    let sandbox = Sandbox::new();
+    for (dir, file) in [
+        ("api", "Cargo.toml"),
+        ("web", "package.json"),
+        (".hidden", "Cargo.toml"),
+    ] {
+        fs::create_dir(sandbox.project().join(dir)).unwrap();
+        fs::write(sandbox.project().join(dir).join(file), "").unwrap();
+    }
+    fs::create_dir(sandbox.project().join("docs")).unwrap();
+
+    let output = sandbox.run(&["--write", "--per-directory"]);
+    assert!(output.status.success());
+    let stdout = self::stdout(&output);
+    assert!(stdout.starts_with("api/.gitignore: rust\n"));
+    assert!(stdout.contains("web/.gitignore: node\n"));
+    let api = fs::read_to_string(sandbox.project().join("api/.gitignore")).unwrap();
+    assert!(api.contains("target/"));
+    let web = fs::read_to_string(sandbox.project().join("web/.gitignore")).unwrap();
+    assert!(web.contains("node_modules/"));
+    assert!(!sandbox.project().join(".hidden/.gitignore").exists());
+    assert!(!sandbox.project().join("docs/.gitignore").exists());
+    assert!(!sandbox.project().join(".gitignore").exists());
+
+    let output = sandbox.run(&["--write", "--per-directory"]);
+    assert!(self::stdout(&output).contains("api/.gitignore: rust (already up to date)\n"));
+}
+
+#[test]
+fn prepends_new_lines_after_leading_comments() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+    fs::write(
+        &gitignore,
+        "# Hand-written rules\n\n/local/\nnode_modules/\n",
+    )
+    .unwrap();
+    let output = sandbox.run(&["Node", "--write", "--prepend"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(content.starts_with("# Hand-written rules\n\n### Sourced from"));
+    assert!(content.ends_with("\n\n/local/\nnode_modules/\n"));
+    assert_eq!(content.matches("node_modules/").count(), 1);
+
+    fs::write(
+        &gitignore,
+        "# Hand-written rules\n\n/local/\nnode_modules/\n",
+    )
+    .unwrap();
+    let output = sandbox.run_direct(&["Node", "--write", "--prepend"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(content.starts_with("# Hand-written rules\n\n### Sourced from"));
+    assert!(content.ends_with("\n\n/local/\nnode_modules/\n"));
+    assert_eq!(content.matches("node_modules/").count(), 1);
+}
+
+#[test]
+fn force_overwrites_instead_of_appending() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+
+    fs::write(&gitignore, "/local/\n").unwrap();
+    assert!(sandbox.run(&["--write", "Rust"]).status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(content.starts_with("/local/\n"));
+    assert!(content.contains("target/"));
+
+    assert!(
+        sandbox
+            .run(&["--write", "--force", "Node"])
+            .status
+            .success()
+    );
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(!content.contains("/local/") && !content.contains("target/"));
+    assert!(content.contains("node_modules/"));
+
+    assert!(
+        sandbox
+            .run_direct(&["--write", "--force", "Rust"])
+            .status
+            .success()
+    );
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(!content.contains("node_modules/"));
+    assert!(content.contains("target/"));
+}
+
+#[test]
+fn force_and_output_imply_write() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+    let excludes = sandbox.dir.path().join("excludes");
+    fs::write(&gitignore, "/local/\n").unwrap();
+    fs::write(&excludes, "/local/\n").unwrap();
+
+    let output = sandbox.run(&["--force", "Rust"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(!content.contains("/local/") && content.contains("target/"));
+
+    let output = sandbox.run(&["--force", "--output", excludes.to_str().unwrap(), "Node"]);
+    assert!(output.status.success());
+    assert!(!stdout(&output).contains("node_modules/"));
+    let content = fs::read_to_string(&excludes).unwrap();
+    assert!(!content.contains("/local/") && content.contains("node_modules/"));
+}
+
+#[test]
+fn warns_about_missing_template() {
+    let output = Sandbox::new().run(&["DoesNotExist"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).is_empty());
+    assert!(stderr(&output).contains("No templates found"));
+}
+
+#[test]
+fn lists_templates_from_cache() {
+    let output = Sandbox::new().run(&["--list"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Global/macOS"));
+    assert!(stdout.contains("Node"));
+    assert!(stdout.contains("Rust"));
+}
+
+#[test]
+fn listing_and_completion_never_clone_the_cache() {
+    let sandbox = Sandbox::new();
+    let cache = sandbox.dir.path().join("cache");
+    for args in [
+        &["--list"][..],
+        &["--list", "rust"],
+        &["completion", "bash"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+            .current_dir(sandbox.project())
+            .args(args)
+            .arg("--cache-dir")
+            .arg(&cache)
+            .arg("--config-dir")
+            .arg(sandbox.dir.path().join("config"))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(stderr(&output), "");
+    }
+    assert!(!cache.exists());
+}
+
+#[test]
+fn strict_fails_on_missing_template() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run(&["--strict", "--write", "Rust", "DoesNotExist"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("DoesNotExist"));
+    assert!(!sandbox.project().join(".gitignore").exists());
+}
+
+#[test]
+fn expands_template_list_files() {
+    let sandbox = Sandbox::new();
+    let list = sandbox.project().join("templates.txt");
+    fs::write(&list, "# My templates\nRust\n\nGlobal/macOS\n").unwrap();
+
+    let output = sandbox.run(&[&format!("@{}", list.display()), "Node"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("target/"));
+    assert!(stdout.contains(".DS_Store"));
+    assert!(stdout.contains("node_modules/"));
+}
+
+#[test]
+fn errors_on_unreadable_template_list_file() {
+    let output = Sandbox::new().run(&["@does-not-exist.txt"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Failed to read template list from does-not-exist.txt"));
+}
+
+#[test]
+fn shows_nested_alias_expansion() {
+    let sandbox = Sandbox::new().with_config(
+        r#"
+[aliases]
+Rust = ["Rust", "Global/macOS"]
+stack = ["Rust", "Node", "Missing"]
+
+[templates]
+"#,
+    );
+    let output = sandbox.run_cmd(&["alias", "show", "stack"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    let lines = stdout.lines().map(str::trim).collect::<Vec<_>>();
+    assert_eq!(
+        lines[1..],
+        ["Rust", "Global/macOS", "Node", "Missing (not found)"]
+    );
+}
+
+#[test]
+fn lists_templates_in_plain_format() {
+    let output = Sandbox::new().run(&["--list", "--format", "plain"]);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "Empty\nGlobal/macOS\nNode\nRust\ncommunity/Golang/Hugo\n"
+    );
+
+    let output = Sandbox::new().run(&["--list", "--verbose", "--format", "plain"]);
+    assert!(!stdout(&output).contains("Built-in synonyms"));
+    let output = Sandbox::new().run(&["--list", "--verbose"]);
+    assert!(stdout(&output).contains("Built-in synonyms:\n  c# => VisualStudio\n"));
+}
+
+#[test]
+fn lists_only_one_kind() {
+    let sandbox = Sandbox::new().with_config(
+        r#"
+[aliases]
+stack = ["Rust", "Node"]
+
+[templates]
+"#,
+    );
+    let output = sandbox.run(&["--list", "--format", "plain", "--only", "aliases"]);
+    assert_eq!(stdout(&output), "stack\n");
+
+    let output = sandbox.run(&["--list", "--format", "plain", "--only", "templates", "o"]);
+    assert_eq!(
+        stdout(&output),
+        "Global/macOS\nNode\ncommunity/Golang/Hugo\n"
+    );
+}
+
+#[test]
+fn lists_templates_as_a_tree() {
+    let sandbox = Sandbox::new();
+    sandbox.run_cmd(&["template", "add", "docs", "--content", "/site/\n"]);
+    let output = sandbox.run(&["--list", "--tree"]);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "  Global/\n    macOS\n  community/\n    Golang/\n      Hugo\n  Empty\n  Node\n  Rust\n  docs\n"
+    );
+
+    let output = sandbox.run(&["--list", "--tree", "Hugo"]);
+    assert_eq!(stdout(&output), "  community/\n    Golang/\n      Hugo\n");
+}
+
+#[test]
+fn reads_templates_from_template_dir() {
+    let template_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/templates");
+    let output = Sandbox::new().run(&[
+        "--template-dir",
+        template_dir.to_str().unwrap(),
+        "Company",
+        "Rust",
+    ]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains(".company/"));
+    assert!(stdout.contains("Cargo.lock"));
+    assert!(!stdout.contains("**/*.rs.bk"));
+}
+
+#[test]
+fn adds_templates_to_the_shared_template_dir() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run_cmd(&["template", "add", "--global", "org", "--content", "/x/\n"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No template directory is configured"));
+
+    let shared = sandbox.dir.path().join("shared");
+    let shared_arg = shared.to_str().unwrap();
+    let output = sandbox.run_cmd(&[
+        "template",
+        "add",
+        "--global",
+        "org",
+        "--content",
+        "/shared/\n",
+        "--template-dir",
+        shared_arg,
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(shared.join("org.gitignore")).unwrap(),
+        "/shared/\n"
+    );
+    let output = sandbox.run(&["--template-dir", shared_arg, "org"]);
+    assert!(self::stdout(&output).contains("/shared/"));
+
+    // The user's own template of the same name takes precedence.
+    sandbox.run_cmd(&["template", "add", "org", "--content", "/own/\n"]);
+    let output = sandbox.run(&["--template-dir", shared_arg, "org"]);
+    let stdout = self::stdout(&output);
+    assert!(stdout.contains("/own/"));
+    assert!(!stdout.contains("/shared/"));
+}
+
+#[test]
+fn warns_about_unknown_names_and_bad_alias_targets() {
+    let sandbox = Sandbox::new().with_config(
+        r#"
+[aliases]
+stack = ["Rust", "Missing"]
+broken = ["Missing"]
+
+[templates]
+"#,
+    );
+    let output = sandbox.run(&["stack", "broken", "Unknown"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("target/"));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("Alias stack refers to unknown template Missing"));
+    let summary = stderr
+        .lines()
+        .find(|line| line.starts_with("Failed to fetch or process template(s): "))
+        .unwrap();
+    assert!(summary.contains("Unknown"));
+    assert!(summary.contains("broken"));
+    assert!(!summary.contains("stack"));
+}
+
+#[test]
+fn uses_custom_banner() {
+    let sandbox = Sandbox::new().with_config(
+        r##"
+banner_template = "# Generated from {templates}"
+
+[aliases]
+
+[templates]
+"##,
+    );
+    let output = sandbox.run(&["Rust"]);
+    let stdout = stdout(&output);
+    assert!(stdout.contains("# Generated from Rust\n"));
+    assert!(!stdout.contains("### Sourced from"));
+}
+
+#[test]
+fn omits_banner() {
+    let output = Sandbox::new().run(&["--no-banner", "Rust"]);
+    let stdout = stdout(&output);
+    assert!(stdout.starts_with("# Generated by Cargo"));
+}
+
+#[test]
+fn prints_template_urls() {
+    let output = Sandbox::new().run_cmd(&["--print-url", "rust", "Global/macOS"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(
+        stdout.contains(
+            "rust: https://raw.githubusercontent.com/github/gitignore/main/Rust.gitignore"
+        )
+    );
+    assert!(stdout.contains("Global/macOS.gitignore"));
+}
+
+#[test]
+fn seeds_user_template_from_existing_template() {
+    let sandbox = Sandbox::new().with_config("[aliases]\n\n[templates]\n");
+    let templates = sandbox.dir.path().join("config/templates");
+    let output = sandbox.run_cmd(&["template", "add", "mine", "--from", "Rust"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(templates.join("mine.ignore")).unwrap();
+    assert!(content.contains("target/"));
+
+    let output = sandbox.run_cmd(&["template", "add", "other", "--from", "Missing"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No template named Missing found"));
+    assert!(!templates.join("other.ignore").exists());
+}
+
+#[test]
+fn includes_the_templates_a_user_template_extends() {
+    let sandbox = Sandbox::new().with_config("[aliases]\n\n[templates]\n");
+    for (name, content) in [
+        ("base", "#!extends Rust\n/base/\n"),
+        ("web", "#!extends base, Node\n/web/\n"),
+        ("first", "#!extends second\n/first/\n"),
+        ("second", "#!extends first\n/second/\n"),
+    ] {
+        let output = sandbox.run_cmd(&["template", "add", name, "--content", content]);
+        assert!(output.status.success());
+    }
+
+    let output = sandbox.run(&["web"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(!stdout.contains("#!extends"));
+    let positions: Vec<usize> = ["target/", "/base/", "node_modules/", "/web/"]
+        .iter()
+        .map(|line| stdout.find(line).unwrap())
+        .collect();
+    assert!(positions.is_sorted());
+
+    let output = sandbox.run(&["first"]);
+    assert!(output.status.success());
+    let stdout = self::stdout(&output);
+    assert!(stdout.find("/second/").unwrap() < stdout.find("/first/").unwrap());
+    assert!(
+        stderr(&output).contains("User template second extends first, which includes it again")
+    );
+}
+
+#[test]
+fn anchors_the_templates_under_a_scope() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run(&["--scope", "frontend", "Rust"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("executables\n/frontend/**/debug/\n/frontend/**/target/\n"));
+    assert!(stdout.contains("/frontend/**/*.rs.bk"));
+
+    let output = sandbox.run_direct(&["--write", "--scope", "frontend/", "Rust"]);
+    assert!(output.status.success());
+    let output = sandbox.run_direct(&["--write", "--scope", "frontend", "Rust"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(sandbox.project().join(".gitignore")).unwrap();
+    assert_eq!(content.matches("/frontend/**/target/").count(), 1);
+    assert!(!content.lines().any(|line| line == "target/"));
+}
+
+#[test]
+fn rejects_an_invalid_proxy() {
+    let output = Sandbox::new().run_direct(&["--proxy", "not a url", "Rust"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Invalid proxy URL 'not a url'"));
+}
+
+#[test]
+fn prints_only_the_patterns_when_bare() {
+    let sandbox = Sandbox::new();
+    for output in [
+        sandbox.run(&["--bare", "Rust"]),
+        sandbox.run_direct(&["--bare", "Rust"]),
+    ] {
+        assert!(output.status.success());
+        let stdout = stdout(&output);
+        assert!(stdout.contains("target/"));
+        assert!(
+            stdout
+                .lines()
+                .all(|line| !line.is_empty() && !line.starts_with('#'))
+        );
+    }
+
+    let output = sandbox.run(&["--bare", "--write", "Rust"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn adds_user_template_with_given_content() {
+    let sandbox = Sandbox::new().with_config("[aliases]\n\n[templates]\n");
+    let templates = sandbox.dir.path().join("config/templates");
+    let source = sandbox.dir.path().join("docs.txt");
+    fs::write(&source, "/site/\n").unwrap();
+
+    let output = sandbox.run_cmd(&[
+        "template",
+        "add",
+        "docs",
+        "--content-file",
+        source.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(templates.join("docs.ignore")).unwrap(),
+        "\n### docs ###\n/site/\n"
+    );
+
+    let output = sandbox.run_cmd(&["template", "add", "inline", "--content", "/out/\n"]);
+    assert!(output.status.success());
+    assert!(
+        fs::read_to_string(templates.join("inline.ignore"))
+            .unwrap()
+            .ends_with("/out/\n")
+    );
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .args(["template", "add", "piped", "--content", "-", "--config-dir"])
+        .arg(sandbox.dir.path().join("config"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"/dist/\n").unwrap();
+    assert!(child.wait().unwrap().success());
+    assert!(
+        fs::read_to_string(templates.join("piped.ignore"))
+            .unwrap()
+            .ends_with("/dist/\n")
+    );
+}
+
+#[test]
+fn filters_stdin_with_the_local_cache() {
+    let sandbox = Sandbox::new();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .current_dir(sandbox.project())
+        .arg("--cache-dir")
+        .arg(fixture_cache())
+        .arg("--config-dir")
+        .arg(sandbox.dir.path().join("config"))
+        .args(["--auto", "--filter", "Rust"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"/local/\ntarget/\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.starts_with("/local/\ntarget/\n"));
+    assert!(stdout.contains("debug/"));
+    assert_eq!(stdout.matches("target/").count(), 1);
+    assert!(!sandbox.project().join(".gitignore").exists());
+}
+
+#[test]
+fn warns_about_shadowed_names_when_verbose() {
+    let sandbox = Sandbox::new().with_config(
+        r#"
+[aliases]
+rust = ["Node"]
+web = ["Node"]
+
+[templates]
+web = "web.ignore"
+"#,
+    );
+    fs::write(
+        sandbox.dir.path().join("config/templates/web.ignore"),
+        "/dist/\n",
+    )
+    .unwrap();
+
+    let stderr = stderr(&sandbox.run_cmd(&["--list", "--verbose"]));
+    assert!(stderr.contains("Warning: Alias rust shadows template Rust\n"));
+    assert!(stderr.contains("Warning: User template web shadows alias web\n"));
+
+    let output = sandbox.run_cmd(&["--list"]);
+    assert!(!self::stderr(&output).contains("shadows"));
+}
+
+#[test]
+fn verifies_generated_sections() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+    assert!(sandbox.run(&["--write", "Rust"]).status.success());
+
+    let output = sandbox.run(&["--verify"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("up to date"));
+
+    // Lines added by hand aren't drift.
+    let mut content = fs::read_to_string(&gitignore).unwrap();
+    content.push_str("\n# Mine\n/scratch/\n");
+    fs::write(&gitignore, &content).unwrap();
+    assert!(sandbox.run(&["--verify"]).status.success());
+
+    fs::write(&gitignore, content.replace("target/\n", "")).unwrap();
+    let output = sandbox.run(&["--verify"]);
+    assert!(!output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("--- a/.gitignore (Rust)\n+++ b/.gitignore (Rust)\n@@ "));
+    assert!(stdout.contains("\n+target/\n"));
+    assert!(!stdout.contains("scratch"));
+}
+
+#[test]
+fn replaces_the_footer_on_every_write() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+    assert!(
+        sandbox
+            .run(&["--write", "--footer", "Rust"])
+            .status
+            .success()
+    );
+    assert!(
+        sandbox
+            .run(&["--write", "--footer", "Node"])
+            .status
+            .success()
+    );
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert_eq!(content.matches("# Generated by git-ignore on").count(), 1);
+    let last = content.lines().last().unwrap();
+    assert!(last.starts_with("# Generated by git-ignore on"));
+    // The fixture cache isn't a git repository.
+    assert!(last.ends_with("from github/gitignore unknown"));
+    assert!(sandbox.run(&["--verify"]).status.success());
+
+    let sandbox = Sandbox::new()
+        .with_config("footer_template = '# Managed by git-ignore'\n\n[aliases]\n\n[templates]\n");
+    assert!(sandbox.run(&["--write", "Rust"]).status.success());
+    let content = fs::read_to_string(sandbox.project().join(".gitignore")).unwrap();
+    assert!(content.ends_with("\n\n# Managed by git-ignore\n"));
+
+    fs::remove_file(sandbox.project().join(".gitignore")).unwrap();
+    assert!(
+        sandbox
+            .run(&["--write", "--no-footer", "Rust"])
+            .status
+            .success()
+    );
+    let content = fs::read_to_string(sandbox.project().join(".gitignore")).unwrap();
+    assert!(!content.contains("Managed by"));
+
+    // Only the last line is the footer, comments that happen to fit it stay.
+    let sandbox =
+        Sandbox::new().with_config("footer_template = '# {commit}'\n\n[aliases]\n\n[templates]\n");
+    let gitignore = sandbox.project().join(".gitignore");
+    fs::write(&gitignore, "# my notes\n/local/\n").unwrap();
+    assert!(sandbox.run(&["--write", "Rust"]).status.success());
+    assert!(sandbox.run(&["--write", "Node"]).status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(content.starts_with("# my notes\n/local/\n"));
+    assert!(content.contains("# Generated by Cargo"));
+    assert!(content.ends_with("node_modules/\n\n# unknown\n"));
+    assert_eq!(content.matches("# unknown").count(), 1);
+
+    // The commit of templates fetched directly isn't known.
+    let sandbox = Sandbox::new();
+    assert!(
+        sandbox
+            .run_direct(&["--write", "--footer", "Rust"])
+            .status
+            .success()
+    );
+    let content = fs::read_to_string(sandbox.project().join(".gitignore")).unwrap();
+    assert!(content.ends_with("from github/gitignore unknown\n"));
+}
+
+#[test]
+fn flattens_global_prefix_in_banner() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run(&["--write", "--flatten-global", "Global/macOS"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(sandbox.project().join(".gitignore")).unwrap();
+    assert!(content.contains("### Sourced from github/gitignore for: macOS ###"));
+    assert!(content.contains(".DS_Store"));
+
+    assert!(sandbox.run(&["--verify"]).status.success());
+}
+
+#[test]
+fn adds_banner_once_when_appending() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+    assert!(sandbox.run(&["--write", "Rust"]).status.success());
+
+    let output = sandbox.run(&["--write", "--append-header-once", "Node"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert_eq!(content.matches("### Sourced from").count(), 1);
+    assert!(content.contains("### Sourced from github/gitignore for: Rust, Node ###"));
+    assert!(content.contains("node_modules/"));
+    assert!(sandbox.run(&["--verify"]).status.success());
+}
+
+#[test]
+fn adds_banner_once_when_appending_directly_fetched_templates() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+    assert!(sandbox.run_direct(&["--write", "Rust"]).status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert!(content.contains("### Sourced from github/gitignore for: Rust ###"));
+
+    let output = sandbox.run_direct(&["--write", "--append-header-once", "Node"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&gitignore).unwrap();
+    assert_eq!(content.matches("### Sourced from").count(), 1);
+    assert!(content.contains("### Sourced from github/gitignore for: Rust, Node ###"));
+    assert!(content.contains("node_modules/"));
+}
+
+#[test]
+fn combines_templates_into_user_template() {
+    let sandbox = Sandbox::new().with_config("[aliases]\n\n[templates]\n");
+    let output = sandbox.run(&["--combine-into", "stack", "Rust", "Node"]);
+    assert!(output.status.success());
+    let template = sandbox.dir.path().join("config/templates/stack.ignore");
+    let content = fs::read_to_string(&template).unwrap();
+    assert!(content.contains("target/"));
+    assert!(content.contains("node_modules/"));
+
+    let output = sandbox.run(&["stack"]);
+    assert!(stdout(&output).contains("node_modules/"));
+
+    let output = sandbox.run(&["--combine-into", "stack", "Rust"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("already exists"));
+    let output = sandbox.run(&["--combine-into", "stack", "--force", "Rust"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(&template).unwrap();
+    assert!(!content.contains("node_modules/"));
+}
+
+#[test]
+fn uses_default_args_from_environment() {
+    let sandbox = Sandbox::new().with_env("GIT_IGNORE_ARGS", "--no-banner --format 'plain'");
+    let output = sandbox.run(&["Rust"]);
+    assert!(output.status.success());
+    assert!(!stdout(&output).contains("### Sourced from"));
+
+    let output = sandbox.run(&["--list", "--format", "pretty", "Rust"]);
+    assert_eq!(stdout(&output), "  Rust\n");
+
+    let output = sandbox.run_cmd(&["alias", "list"]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn prints_diff_without_writing() {
+    let sandbox = Sandbox::new();
+    let gitignore = sandbox.project().join(".gitignore");
+
+    let output = sandbox.run_direct(&["--diff", "Rust"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.starts_with("--- /dev/null\n+++ b/.gitignore\n@@ -0,0 +1,"));
+    assert!(stdout.contains("\n+target/\n"));
+    assert!(!gitignore.exists());
+
+    fs::write(&gitignore, "*.log\n").unwrap();
+    let output = sandbox.run(&["--diff", "Node"]);
+    assert!(output.status.success());
+    let stdout = self::stdout(&output);
+    assert!(stdout.starts_with("--- a/.gitignore\n+++ b/.gitignore\n@@ -1 +1,"));
+    assert!(stdout.contains("\n *.log\n"));
+    assert!(stdout.contains("\n+node_modules/\n"));
+    assert_eq!(fs::read_to_string(&gitignore).unwrap(), "*.log\n");
+}
+
+#[test]
+fn serves_direct_fetches_from_the_response_cache() {
+    let sandbox = Sandbox::new();
+    let cached = sandbox.cache_response(&format!("{GITHUB_BASE_URL}Go.gitignore"), "/cached/\n");
+    // Nothing can be fetched through this proxy, so only cached responses work.
+    let run = |args: &[&str]| {
+        let args = [&["--proxy", "http://127.0.0.1:9", "Go"], args].concat();
+        sandbox.run_direct(&args)
+    };
+
+    let output = run(&[]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("/cached/\n"));
+
+    let output = run(&["--no-cache"]);
+    assert!(!stdout(&output).contains("/cached/"));
+    assert!(stderr(&output).contains("Failed to fetch template 'Go'"));
+
+    let expired = std::time::SystemTime::now() - std::time::Duration::from_secs(25 * 60 * 60);
+    fs::File::options()
+        .write(true)
+        .open(&cached)
+        .unwrap()
+        .set_modified(expired)
+        .unwrap();
+    let output = run(&[]);
+    assert!(!stdout(&output).contains("/cached/"));
+    assert!(stderr(&output).contains("Failed to fetch template 'Go'"));
+}
+
+#[test]
+fn fetches_combined_templates_from_gitignore_io() {
+    let sandbox = Sandbox::new();
+    sandbox.cache_response(
+        "https://www.toptal.com/developers/gitignore/api/rust,node",
+        "# Created by https://www.toptal.com/developers/gitignore/api/rust,node\n\
+         target/\nnode_modules/\n",
+    );
+
+    let output = sandbox.run_direct(&[
+        "--proxy",
+        "http://127.0.0.1:9",
+        "--source",
+        "gitignore.io",
+        "Rust",
+        "Node",
+    ]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(
+        stdout.contains("# Created by https://www.toptal.com/developers/gitignore/api/rust,node\n")
+    );
+    assert!(stdout.contains("target/\nnode_modules/\n"));
+    assert!(!stdout.contains("### Sourced from"));
+}
+
+#[test]
+fn skips_lines_from_against_file() {
+    let sandbox = Sandbox::new();
+    let shared = sandbox.dir.path().join("shared.gitignore");
+    fs::write(&shared, "target/\ndebug/\n").unwrap();
+
+    let output = sandbox.run_direct(&["--against", shared.to_str().unwrap(), "Rust"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("**/*.rs.bk\n"));
+    assert!(!stdout.contains("target/"));
+    assert!(!stdout.contains("debug/"));
+
+    let output = sandbox.run(&["--against", shared.to_str().unwrap(), "Rust"]);
+    assert!(output.status.success());
+    let stdout = self::stdout(&output);
+    assert!(stdout.contains("**/*.rs.bk\n"));
+    assert!(!stdout.contains("target/") && !stdout.contains("debug/"));
+
+    let output = sandbox.run_direct(&["--against", "missing.gitignore", "Rust"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Failed to read missing.gitignore"));
+}
+
+#[test]
+fn skips_lines_from_git_exclude() {
+    let sandbox = Sandbox::new();
+    let info = sandbox.project().join(".git/info");
+    fs::create_dir_all(&info).unwrap();
+    fs::write(info.join("exclude"), "target/\n").unwrap();
+
+    let stdout = stdout(&sandbox.run_direct(&["--respect-git-exclude", "Rust"]));
+    assert!(stdout.contains("debug/\n"));
+    assert!(!stdout.contains("target/"));
+
+    let output = sandbox.run(&["--write", "--respect-git-exclude", "Rust"]);
+    assert!(output.status.success());
+    let content = fs::read_to_string(sandbox.project().join(".gitignore")).unwrap();
+    assert!(content.contains("debug/\n") && !content.contains("target/"));
+}
+
+#[test]
+fn lists_aliases_expanded() {
+    let sandbox = Sandbox::new().with_config(
+        r#"
+[aliases]
+web = ["Node", "Missing"]
+stack = ["Rust", "web"]
+
+[templates]
+"#,
+    );
+    let output = sandbox.run_cmd(&["alias", "list", "--expanded"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(
+        stdout.contains("stack => [\"Rust\", \"web\"]\n  Rust\n  Node\n  Missing (not found)\n")
+    );
+    assert!(stdout.contains("web => [\"Node\", \"Missing\"]\n  Node\n  Missing (not found)\n"));
+}
+
+#[test]
+fn explains_how_names_resolve() {
+    let sandbox = Sandbox::new().with_config(
+        r#"
+[aliases]
+web = ["Node", "Missing"]
+
+[templates]
+docs = "docs.ignore"
+"#,
+    );
+    fs::write(
+        sandbox.dir.path().join("config/templates/docs.ignore"),
+        "/site/\n",
+    )
+    .unwrap();
+
+    let stdout = stdout(&sandbox.run_cmd(&["--explain", "rust"]));
+    let path = fixture_cache().join("github_gitignore_repo/Rust.gitignore");
+    assert!(stdout.starts_with(&format!("rust: template at {}\n", path.display())));
+    assert!(stdout.contains(
+        "  url: https://raw.githubusercontent.com/github/gitignore/main/Rust.gitignore\n"
+    ));
+
+    let stdout = self::stdout(&sandbox.run_cmd(&["--explain", "web"]));
+    assert!(stdout.starts_with("web: alias => [\"Node\", \"Missing\"]\n  Node: template at "));
+    assert!(stdout.ends_with("  Missing: not found\n"));
+
+    let stdout = self::stdout(&sandbox.run_cmd(&["--explain", "docs"]));
+    let path = sandbox.dir.path().join("config/templates/docs.ignore");
+    assert_eq!(
+        stdout,
+        format!("docs: user template at {}\n", path.display())
+    );
+
+    let output = sandbox.run_cmd(&["--explain", "unknown"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No template, alias or user template named unknown found"));
+}
+
+#[test]
+fn orders_global_templates_last_unless_preserved() {
+    let sandbox = Sandbox::new();
+    let stdout = stdout(&sandbox.run(&["Global/macOS", "Rust"]));
+    assert!(stdout.contains("for: Rust, Global/macOS ###"));
+    assert!(stdout.find("target/") < stdout.find(".DS_Store"));
+
+    let output = sandbox.run(&["--preserve-order", "Global/macOS", "Rust"]);
+    let stdout = self::stdout(&output);
+    assert!(stdout.contains("for: Global/macOS, Rust ###"));
+    assert!(stdout.find(".DS_Store") < stdout.find("target/"));
+}